@@ -0,0 +1,113 @@
+use sdl2::ttf::FontStyle;
+
+use crate::renderer::{font_for, scaled, spaced_text_width, LineItem, RendererContext};
+use crate::style::{Direction, TextAlign};
+
+// Move the items of a line to match `text_align`, which is resolved to left, right, center or
+// justify by now, within a content box ending at `right`
+pub fn align_line(
+    context: &RendererContext,
+    mut line: Vec<LineItem>,
+    text_align: TextAlign,
+    right: u32,
+) -> Vec<LineItem> {
+    let end = line
+        .iter()
+        .map(|item| item.x + item.width)
+        .max()
+        .unwrap_or(0);
+    let free = right.saturating_sub(end);
+    if free == 0 {
+        return line;
+    }
+    match text_align {
+        TextAlign::Right | TextAlign::Center => {
+            let shift = if text_align == TextAlign::Right {
+                free
+            } else {
+                free / 2
+            };
+            for item in &mut line {
+                item.x += shift;
+            }
+            line
+        }
+        TextAlign::Justify => justify(context, line, free),
+        _ => line,
+    }
+}
+
+fn spaces(item: &LineItem) -> usize {
+    match item.control {
+        Some(_) => 0,
+        None => item.text.matches(' ').count(),
+    }
+}
+
+// Share `free` out between the spaces of a line and the gaps between its items, splitting the
+// items into words
+fn justify(context: &RendererContext, line: Vec<LineItem>, free: u32) -> Vec<LineItem> {
+    let mut order: Vec<usize> = (0..line.len()).collect();
+    order.sort_by_key(|&i| line[i].x);
+    let gaps = order
+        .windows(2)
+        .filter(|pair| line[pair[1]].x > line[pair[0]].x + line[pair[0]].width)
+        .count();
+    let opportunities = (gaps + line.iter().map(spaces).sum::<usize>()) as u32;
+    if opportunities == 0 {
+        return line;
+    }
+    let mut shared = 0;
+    let mut share = || {
+        shared += 1;
+        free * shared / opportunities - free * (shared - 1) / opportunities
+    };
+    // Pieces of every item in logical order, placed from left to right
+    let mut pieces: Vec<Vec<LineItem>> = vec![Vec::new(); line.len()];
+    let mut x = line[order[0]].x;
+    let mut previous_end = None;
+    for i in order {
+        let item = &line[i];
+        if let Some(end) = previous_end.filter(|&end| item.x > end) {
+            x += item.x - end + share();
+        }
+        previous_end = Some(item.x + item.width);
+        if spaces(item) == 0 {
+            pieces[i].push(LineItem { x, ..item.clone() });
+            x += item.width;
+            continue;
+        }
+        let rtl = item.style.direction == Direction::Rtl;
+        let mut words: Vec<&str> = item.text.split_inclusive(' ').collect();
+        if rtl {
+            words.reverse();
+        }
+        let font = font_for(context, &item.style);
+        font.borrow_mut().set_style(item.style.font_style);
+        for word in words {
+            let width = spaced_text_width(
+                context,
+                &item.style,
+                &font.borrow(),
+                word,
+                scaled(context, item.style.font_size),
+            );
+            pieces[i].push(LineItem {
+                text: word.to_string(),
+                x,
+                width,
+                style: item.style.clone(),
+                control: None,
+            });
+            x += width;
+            if word.ends_with(' ') {
+                x += share();
+            }
+        }
+        font.borrow_mut().set_style(FontStyle::NORMAL);
+        if rtl {
+            pieces[i].reverse();
+        }
+    }
+    pieces.into_iter().flatten().collect()
+}
@@ -0,0 +1,232 @@
+use std::ops::Range;
+
+use sdl2::ttf::FontStyle;
+use unicode_bidi::{bidi_class, BidiClass, BidiInfo, Level};
+
+use crate::renderer::{font_for, scaled, spaced_text_width, LineItem, RendererContext};
+use crate::style::{Direction, Style, UnicodeBidi};
+
+// Characters that can make a line anything other than plain left-to-right text
+fn affects_order(c: char) -> bool {
+    use BidiClass::*;
+    matches!(
+        bidi_class(c),
+        R | AL | AN | LRE | LRO | LRI | RLE | RLO | RLI | FSI | PDF | PDI
+    )
+}
+
+// Control characters that start an embedding, isolate or override
+fn opening(embedding: &(usize, UnicodeBidi, Direction)) -> &'static str {
+    let rtl = embedding.2 == Direction::Rtl;
+    match embedding.1 {
+        UnicodeBidi::Normal => "",
+        UnicodeBidi::Embed if rtl => "\u{202B}",
+        UnicodeBidi::Embed => "\u{202A}",
+        UnicodeBidi::Isolate if rtl => "\u{2067}",
+        UnicodeBidi::Isolate => "\u{2066}",
+        UnicodeBidi::IsolateAuto => "\u{2068}",
+        UnicodeBidi::Override if rtl => "\u{202E}",
+        UnicodeBidi::Override => "\u{202D}",
+        UnicodeBidi::IsolateOverride if rtl => "\u{2067}\u{202E}",
+        UnicodeBidi::IsolateOverride => "\u{2066}\u{202D}",
+    }
+}
+
+fn closing(embedding: &(usize, UnicodeBidi, Direction)) -> &'static str {
+    match embedding.1 {
+        UnicodeBidi::Normal => "",
+        UnicodeBidi::Embed | UnicodeBidi::Override => "\u{202C}",
+        UnicodeBidi::Isolate | UnicodeBidi::IsolateAuto => "\u{2069}",
+        UnicodeBidi::IsolateOverride => "\u{202C}\u{2069}",
+    }
+}
+
+// Text of a right-to-left run in the order its characters are drawn, for fonts that can't
+// shape it, with brackets mirrored
+pub fn visual_order(text: &str) -> String {
+    text.chars()
+        .rev()
+        .map(|c| match c {
+            '(' => ')',
+            ')' => '(',
+            '[' => ']',
+            ']' => '[',
+            '{' => '}',
+            '}' => '{',
+            '<' => '>',
+            '>' => '<',
+            '«' => '»',
+            '»' => '«',
+            c => c,
+        })
+        .collect()
+}
+
+// Segments of the paragraph text: a range, the item it comes from (None for the space standing
+// in for a gap between items) and its width
+type Segments = Vec<(Range<usize>, Option<usize>, u32)>;
+
+// Run the bidi algorithm on the lines of a paragraph, then split the items of each line into runs
+// of one direction and place them in visual order from `left`. Returns the lines with whether the
+// paragraph is right-to-left. `base` is None when the direction comes from the first strong
+// character of the paragraph.
+pub fn reorder_paragraph(
+    context: &RendererContext,
+    lines: Vec<Vec<LineItem>>,
+    base: Option<Direction>,
+    left: u32,
+) -> (Vec<Vec<LineItem>>, bool) {
+    if base == Some(Direction::Ltr)
+        && lines
+            .iter()
+            .flatten()
+            .all(|item| item.style.embeddings.is_empty() && !item.text.chars().any(affects_order))
+    {
+        return (lines, false);
+    }
+    // The text of the paragraph with control characters around embedded items, a space
+    // standing in for every gap between items and one between lines
+    let mut text = String::new();
+    let mut line_ranges = Vec::new();
+    let mut line_segments: Vec<Segments> = Vec::new();
+    let mut open: &[(usize, UnicodeBidi, Direction)] = &[];
+    for line in &lines {
+        if !line_ranges.is_empty() {
+            text.push(' ');
+        }
+        let line_start = text.len();
+        let mut segments = Vec::new();
+        let mut end = left;
+        for (i, item) in line.iter().enumerate() {
+            let embeddings = &item.style.embeddings;
+            let shared = open
+                .iter()
+                .zip(embeddings)
+                .take_while(|(a, b)| a.0 == b.0)
+                .count();
+            for embedding in open[shared..].iter().rev() {
+                text.push_str(closing(embedding));
+            }
+            if item.x > end {
+                segments.push((text.len()..text.len() + 1, None, item.x - end));
+                text.push(' ');
+            }
+            for embedding in &embeddings[shared..] {
+                text.push_str(opening(embedding));
+            }
+            let start = text.len();
+            match item.control {
+                Some(_) => text.push('\u{FFFC}'),
+                None => text.push_str(&item.text),
+            }
+            segments.push((start..text.len(), Some(i), item.width));
+            open = embeddings;
+            end = item.x + item.width;
+        }
+        line_ranges.push(line_start..text.len());
+        line_segments.push(segments);
+    }
+    for embedding in open.iter().rev() {
+        text.push_str(closing(embedding));
+    }
+    let level = base.map(|direction| match direction {
+        Direction::Ltr => Level::ltr(),
+        Direction::Rtl => Level::rtl(),
+    });
+    let bidi = BidiInfo::new(&text, level);
+    let rtl = match bidi.paragraphs.first() {
+        Some(paragraph) => paragraph.level.is_rtl(),
+        None => return (lines, false),
+    };
+    let lines = lines
+        .into_iter()
+        .zip(line_ranges)
+        .zip(line_segments)
+        .map(|((line, range), segments)| {
+            // Text that looks like a paragraph separator can split the text further
+            let paragraph = bidi
+                .paragraphs
+                .iter()
+                .rev()
+                .find(|paragraph| paragraph.range.start <= range.start)
+                .unwrap();
+            let levels = bidi.reordered_levels(paragraph, range);
+            reorder_line(context, &text, &levels, line, segments, left)
+        })
+        .collect();
+    (lines, rtl)
+}
+
+// Split the items of a line into pieces with a single level, using the levels of the paragraph
+// text, and place them in visual order from `left`
+fn reorder_line(
+    context: &RendererContext,
+    text: &str,
+    levels: &[Level],
+    line: Vec<LineItem>,
+    segments: Segments,
+    left: u32,
+) -> Vec<LineItem> {
+    // Pieces of items with a single level, with their width
+    let mut pieces: Vec<(Level, Range<usize>, Option<usize>, u32)> = Vec::new();
+    for (range, item, width) in segments {
+        let start = pieces.len();
+        let mut from = range.start;
+        for (offset, _) in text[range.clone()].char_indices().skip(1) {
+            let offset = range.start + offset;
+            if levels[offset] != levels[from] {
+                pieces.push((levels[from], from..offset, item, 0));
+                from = offset;
+            }
+        }
+        pieces.push((levels[from], from..range.end, item, 0));
+        if pieces.len() - start == 1 {
+            pieces[start].3 = width;
+            continue;
+        }
+        let style = &line[item.unwrap()].style;
+        let font = font_for(context, style);
+        font.borrow_mut().set_style(style.font_style);
+        for piece in &mut pieces[start..] {
+            piece.3 = spaced_text_width(
+                context,
+                style,
+                &font.borrow(),
+                &text[piece.1.clone()],
+                scaled(context, style.font_size),
+            );
+        }
+        font.borrow_mut().set_style(FontStyle::NORMAL);
+    }
+    let mut x = left;
+    let mut positions = vec![0; pieces.len()];
+    let piece_levels: Vec<Level> = pieces.iter().map(|piece| piece.0).collect();
+    for i in BidiInfo::reorder_visual(&piece_levels) {
+        positions[i] = x;
+        x += pieces[i].3;
+    }
+    pieces
+        .into_iter()
+        .zip(positions)
+        .filter_map(|((level, range, item, width), x)| {
+            let item = &line[item?];
+            Some(LineItem {
+                text: match item.control {
+                    Some(_) => String::new(),
+                    None => text[range].to_string(),
+                },
+                x,
+                width,
+                style: Style {
+                    direction: if level.is_rtl() {
+                        Direction::Rtl
+                    } else {
+                        Direction::Ltr
+                    },
+                    ..item.style.clone()
+                },
+                control: item.control,
+            })
+        })
+        .collect()
+}
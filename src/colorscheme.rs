@@ -1,13 +1,34 @@
 use clap::ArgEnum;
 use sdl2::pixels::Color;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
 use std::ops::Deref;
+use std::path::PathBuf;
+use std::process::Command;
 use strum::IntoEnumIterator; // 0.17.1
 use strum_macros::{EnumIter, EnumString}; // 0.17.1
-#[derive(Clone)]
+
+use crate::style::parse_color;
+
+#[derive(Clone, Debug)]
 pub struct ColorScheme {
     pub background: Color,
     pub text: Color,
     pub link: Color,
+    pub visited_link: Color,
+    pub selection: Color,
+    // Backgrounds of find in page matches and of the current one
+    pub find_match: Color,
+    pub current_find_match: Color,
+    pub heading: Color,
+    // Background and text of `<mark>`
+    pub mark: Color,
+    pub mark_text: Color,
+    // Drawn behind monospace text, if the theme has one
+    pub code_background: Option<Color>,
+    pub focus_ring: Color,
+    pub scrollbar: Color,
 }
 impl Default for ColorScheme {
     fn default() -> ColorScheme {
@@ -15,9 +36,26 @@ impl Default for ColorScheme {
             background: Color::RGB(255, 255, 255),
             text: Color::RGB(0, 0, 0),
             link: Color::RGB(0, 0, 238),
+            visited_link: Color::RGB(85, 26, 139),
+            selection: Color::RGB(179, 215, 255),
+            find_match: Color::RGB(255, 255, 0),
+            current_find_match: Color::RGB(255, 150, 50),
+            heading: Color::RGB(0, 0, 0),
+            mark: Color::RGB(255, 255, 0),
+            mark_text: Color::RGB(0, 0, 0),
+            code_background: None,
+            focus_ring: Color::RGB(0, 0, 238),
+            scrollbar: Color::RGB(160, 160, 160),
         }
     }
 }
+impl ColorScheme {
+    // Whether the background is dark, which is what `prefers-color-scheme` reports to pages
+    pub fn is_dark(&self) -> bool {
+        let Color { r, g, b, .. } = self.background;
+        (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000 < 128
+    }
+}
 #[derive(EnumString, EnumIter, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
 pub enum DefaultColorSchemes {
     Standard,
@@ -31,7 +69,182 @@ impl DefaultColorSchemes {
                 background: Color::RGB(30, 30, 46),
                 text: Color::RGB(217, 224, 238),
                 link: Color::RGB(245, 224, 220),
+                visited_link: Color::RGB(203, 166, 247),
+                selection: Color::RGB(88, 91, 112),
+                find_match: Color::RGB(98, 86, 58),
+                current_find_match: Color::RGB(150, 92, 60),
+                heading: Color::RGB(180, 190, 254),
+                mark: Color::RGB(249, 226, 175),
+                mark_text: Color::RGB(30, 30, 46),
+                code_background: Some(Color::RGB(49, 50, 68)),
+                focus_ring: Color::RGB(137, 180, 250),
+                scrollbar: Color::RGB(108, 112, 134),
             },
         }
     }
+
+    pub fn name(&self) -> String {
+        format!("{:?}", self).to_lowercase()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Appearance {
+    Light,
+    Dark,
+}
+
+// Output of a command, if it ran and succeeded
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// The color-scheme setting of the freedesktop settings portal, None when it has no preference
+fn portal_appearance() -> Option<Appearance> {
+    let reply = command_output(
+        "gdbus",
+        &[
+            "call",
+            "--session",
+            "--dest",
+            "org.freedesktop.portal.Desktop",
+            "--object-path",
+            "/org/freedesktop/portal/desktop",
+            "--method",
+            "org.freedesktop.portal.Settings.Read",
+            "org.freedesktop.appearance",
+            "color-scheme",
+        ],
+    )?;
+    // The reply looks like `(<<uint32 1>>,)`, where 1 is dark, 2 light and 0 no preference
+    match reply.split("uint32").nth(1)?.trim_start().chars().next()? {
+        '1' => Some(Appearance::Dark),
+        '2' => Some(Appearance::Light),
+        _ => None,
+    }
+}
+
+// GNOME's settings, for desktops without the portal
+fn gsettings_appearance() -> Option<Appearance> {
+    let setting = |key| command_output("gsettings", &["get", "org.gnome.desktop.interface", key]);
+    match setting("color-scheme").as_deref() {
+        Some("'prefer-dark'") => Some(Appearance::Dark),
+        Some("'prefer-light'") => Some(Appearance::Light),
+        _ => theme_appearance(&setting("gtk-theme")?),
+    }
+}
+
+// Dark variants of GTK themes are named like `Adwaita:dark` or `Adwaita-dark`
+fn theme_appearance(theme: &str) -> Option<Appearance> {
+    if theme.trim_matches('\'').is_empty() {
+        None
+    } else if theme.to_lowercase().contains("dark") {
+        Some(Appearance::Dark)
+    } else {
+        Some(Appearance::Light)
+    }
+}
+
+// Light or dark preference of the desktop, asking the settings portal, then GNOME's settings
+// and then the GTK_THEME environment variable. Desktops that tell us nothing are light.
+pub fn desktop_appearance() -> Appearance {
+    portal_appearance()
+        .or_else(gsettings_appearance)
+        .or_else(|| theme_appearance(&env::var("GTK_THEME").ok()?))
+        .unwrap_or(Appearance::Light)
+}
+
+// Directory of the user's themes, one TOML file per theme named after the file
+pub fn themes_dir() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("opilio").join("themes"))
+}
+
+// Names of the built-in themes followed by those of the user's themes
+pub fn theme_names() -> Vec<String> {
+    let mut names: Vec<String> = DefaultColorSchemes::iter().map(|s| s.name()).collect();
+    names.push("auto".to_string());
+    let mut user_names: Vec<String> = themes_dir()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "toml" {
+                return None;
+            }
+            Some(path.file_stem()?.to_str()?.to_string())
+        })
+        .filter(|name| !names.contains(name))
+        .collect();
+    user_names.sort();
+    names.append(&mut user_names);
+    names
+}
+
+fn built_in(name: &str) -> Option<ColorScheme> {
+    DefaultColorSchemes::iter()
+        .find(|scheme| scheme.name() == name.to_lowercase())
+        .map(|scheme| scheme.value())
+}
+
+// Parse a theme file. Every key names a slot of the scheme and holds a CSS colour, slots that
+// are left out come from the built-in theme named by `base`, or the standard theme.
+fn parse_theme(source: &str) -> Result<ColorScheme, String> {
+    let slots: HashMap<String, String> = toml::from_str(source).map_err(|e| e.to_string())?;
+    let mut scheme = match slots.get("base") {
+        Some(base) => built_in(base).ok_or_else(|| format!("Unknown base theme: {}", base))?,
+        None => ColorScheme::default(),
+    };
+    for (slot, value) in slots.iter().filter(|(slot, _)| slot.as_str() != "base") {
+        let value = value.trim().to_lowercase();
+        if slot == "code_background" && value == "none" {
+            scheme.code_background = None;
+            continue;
+        }
+        let color = parse_color(&value).ok_or_else(|| format!("Invalid colour: {}", value))?;
+        match slot.as_str() {
+            "background" => scheme.background = color,
+            "text" => scheme.text = color,
+            "link" => scheme.link = color,
+            "visited_link" => scheme.visited_link = color,
+            "selection" => scheme.selection = color,
+            "find_match" => scheme.find_match = color,
+            "current_find_match" => scheme.current_find_match = color,
+            "heading" => scheme.heading = color,
+            "mark" => scheme.mark = color,
+            "mark_text" => scheme.mark_text = color,
+            "code_background" => scheme.code_background = Some(color),
+            "focus_ring" => scheme.focus_ring = color,
+            "scrollbar" => scheme.scrollbar = color,
+            _ => return Err(format!("Unknown colour slot: {}", slot)),
+        }
+    }
+    Ok(scheme)
+}
+
+// Look a theme up by name, the user's themes take precedence over built-ins of the same name.
+// `auto` picks the standard or catppuccin theme to match the desktop.
+pub fn load_theme(name: &str) -> Result<ColorScheme, String> {
+    if let Some(path) = themes_dir().map(|dir| dir.join(format!("{}.toml", name))) {
+        if let Ok(source) = fs::read_to_string(&path) {
+            return parse_theme(&source).map_err(|e| format!("{}: {}", path.display(), e));
+        }
+    }
+    if name.eq_ignore_ascii_case("auto") {
+        return Ok(match desktop_appearance() {
+            Appearance::Light => DefaultColorSchemes::Standard.value(),
+            Appearance::Dark => DefaultColorSchemes::Catppuccin.value(),
+        });
+    }
+    built_in(name).ok_or_else(|| {
+        format!(
+            "Unknown colour theme: {} (available: {})",
+            name,
+            theme_names().join(", ")
+        )
+    })
 }
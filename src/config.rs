@@ -0,0 +1,108 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+// Settings that used to be compiled in. Built-in defaults are overridden by the config file,
+// then by OPILIO_* environment variables and then by command line flags.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // Initial size of the window in screen coordinates
+    pub window_width: u32,
+    pub window_height: u32,
+    // Font files tried before the system fonts opilio knows about
+    pub font: Option<String>,
+    pub mono_font: Option<String>,
+    // Size of body text in CSS pixels
+    pub font_size: u32,
+    // Distance scrolled by a mouse wheel step in CSS pixels
+    pub scroll_speed: i32,
+    pub theme: String,
+    // Where fetched images are kept between runs. The cache ignores the caching headers of
+    // responses, so it is off unless a directory is given.
+    pub cache_dir: Option<PathBuf>,
+    // Start with the hit rects of the debug overlay shown, F12 toggles it
+    pub draw_hit_rects: bool,
+}
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            window_width: 800,
+            window_height: 600,
+            font: None,
+            mono_font: None,
+            font_size: 16,
+            scroll_speed: 12,
+            theme: "standard".to_string(),
+            cache_dir: None,
+            draw_hit_rects: false,
+        }
+    }
+}
+
+// The config file in the config directory, which is $XDG_CONFIG_HOME/opilio on Linux
+pub fn default_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("opilio").join("config.toml"))
+}
+
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", key, value))
+}
+
+impl Config {
+    // Defaults overridden by the config file at `path`, or the default one if it exists, and
+    // then by the environment
+    pub fn load(path: Option<PathBuf>) -> Result<Config, String> {
+        let required = path.is_some();
+        let mut config = match path.or_else(default_path) {
+            Some(path) => match fs::read_to_string(&path) {
+                Ok(source) => {
+                    toml::from_str(&source).map_err(|e| format!("{}: {}", path.display(), e))?
+                }
+                Err(e) if required => return Err(format!("{}: {}", path.display(), e)),
+                Err(_) => Config::default(),
+            },
+            None => Config::default(),
+        };
+        config.cache_dir = config.cache_dir.filter(|dir| !dir.as_os_str().is_empty());
+        for (name, value) in env::vars() {
+            // Other programs' variables can share the prefix, so unknown ones aren't fatal
+            if let Some(key) = name.strip_prefix("OPILIO_") {
+                if !config.set(&key.to_lowercase(), &value)? {
+                    println!("Ignoring unknown setting in {}", name);
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    // Set a setting from its text, as given in an environment variable. Returns false when
+    // `key` isn't a setting.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<bool, String> {
+        // Paths can be left empty to unset them
+        let path = Some(value).filter(|value| !value.is_empty());
+        match key {
+            "window_width" => self.window_width = parse(key, value)?,
+            "window_height" => self.window_height = parse(key, value)?,
+            "font" => self.font = path.map(|value| value.to_string()),
+            "mono_font" => self.mono_font = path.map(|value| value.to_string()),
+            "font_size" => self.font_size = parse(key, value)?,
+            "scroll_speed" => self.scroll_speed = parse(key, value)?,
+            "theme" => self.theme = value.to_string(),
+            "cache_dir" => self.cache_dir = path.map(PathBuf::from),
+            "draw_hit_rects" => {
+                self.draw_hit_rects = match value {
+                    "1" | "true" | "yes" => true,
+                    "0" | "false" | "no" => false,
+                    _ => return Err(format!("Invalid value for {}: {}", key, value)),
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,412 @@
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use url::Url;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+// A cookie as stored by the jar, see RFC 6265 section 5.3
+#[derive(Clone, Debug)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    // Only sent to the exact host that set it, as no Domain attribute was given
+    pub host_only: bool,
+    pub path: String,
+    // Seconds since the epoch, session cookies have none and are never saved
+    pub expires: Option<u64>,
+    pub secure: bool,
+    // Kept away from scripts, which opilio doesn't run, so this is only stored
+    pub http_only: bool,
+    pub same_site: SameSite,
+    pub creation: u64,
+}
+
+// What caused a request, which decides whether SameSite cookies go along with it
+#[derive(Clone, Copy, Debug)]
+pub enum Initiator<'a> {
+    // Typed in, given on the command line or reloaded from the history
+    User,
+    // Following a link or submitting a form on a document
+    Navigation(&'a Url),
+    // An image or other resource of a document
+    Subresource(&'a Url),
+}
+
+#[derive(Clone, Default)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+    // File the persistent cookies are loaded from and saved to
+    path: Option<String>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+// Whether `host` is `domain` or one of its subdomains
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.ends_with(domain)
+            && host[..host.len() - domain.len()].ends_with('.')
+            && host.parse::<std::net::IpAddr>().is_err())
+}
+
+// Whether a cookie path covers the path of a request
+fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+// The directory of the request path, used when a cookie has no Path attribute
+fn default_path(location: &Url) -> String {
+    let path = location.path();
+    match path.rfind('/') {
+        Some(0) | None => String::from("/"),
+        Some(i) => path[..i].to_string(),
+    }
+}
+
+// Last two labels of a host, which stand in for the registrable domain as there is no public
+// suffix list to look it up in
+fn site(location: &Url) -> Option<String> {
+    let host = location.host_str()?.to_ascii_lowercase();
+    let labels: Vec<&str> = host.rsplitn(3, '.').collect();
+    Some(match labels[..] {
+        [top, second, _] => format!("{}.{}", second, top),
+        _ => host,
+    })
+}
+
+impl CookieJar {
+    // A jar saved to `path` when the browser quits, starting with the cookies saved there
+    pub fn load(path: Option<String>) -> CookieJar {
+        let mut jar = CookieJar {
+            cookies: Vec::new(),
+            path,
+        };
+        let contents = match &jar.path {
+            Some(path) => fs::read_to_string(path).unwrap_or_default(),
+            None => return jar,
+        };
+        let now = now();
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if let [domain, host_only, path, secure, http_only, same_site, expires, creation, name, value] =
+                fields[..]
+            {
+                let cookie = Cookie {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                    domain: domain.to_string(),
+                    host_only: host_only == "TRUE",
+                    path: path.to_string(),
+                    expires: expires.parse().ok(),
+                    secure: secure == "TRUE",
+                    http_only: http_only == "TRUE",
+                    same_site: match same_site {
+                        "Strict" => SameSite::Strict,
+                        "None" => SameSite::None,
+                        _ => SameSite::Lax,
+                    },
+                    creation: creation.parse().unwrap_or(now),
+                };
+                if cookie.expires.is_some_and(|expires| expires > now) {
+                    jar.cookies.push(cookie);
+                }
+            }
+        }
+        jar
+    }
+
+    // Write the cookies that outlive the session to the jar's file, if it has one
+    pub fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let now = now();
+        let flag = |flag: bool| if flag { "TRUE" } else { "FALSE" };
+        let contents: String = self
+            .cookies
+            .iter()
+            .filter(|cookie| cookie.expires.is_some_and(|expires| expires > now))
+            .map(|cookie| {
+                format!(
+                    "{}\t{}\t{}\t{}\t{}\t{:?}\t{}\t{}\t{}\t{}\n",
+                    cookie.domain,
+                    flag(cookie.host_only),
+                    cookie.path,
+                    flag(cookie.secure),
+                    flag(cookie.http_only),
+                    cookie.same_site,
+                    cookie.expires.unwrap(),
+                    cookie.creation,
+                    cookie.name,
+                    cookie.value
+                )
+            })
+            .collect();
+        if let Err(e) = fs::write(path, contents) {
+            println!("Couldn't save cookies to {}: {}", path, e);
+        }
+    }
+
+    // Store a cookie from a `Set-Cookie` header of a response for `location`
+    pub fn set_cookie(&mut self, location: &Url, header: &str) {
+        let host = match location.host_str() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => return,
+        };
+        let mut parts = header.split(';');
+        let (name, value) = match parts.next().and_then(|pair| pair.split_once('=')) {
+            Some((name, value)) if !name.trim().is_empty() => (name.trim(), value.trim()),
+            _ => return,
+        };
+        let now = now();
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(location),
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: SameSite::Lax,
+            creation: now,
+        };
+        let mut max_age = None;
+        for attribute in parts {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "expires" => {
+                    if let Ok(time) = httpdate::parse_http_date(value) {
+                        cookie.expires = Some(
+                            time.duration_since(UNIX_EPOCH)
+                                .map(|time| time.as_secs())
+                                .unwrap_or_default(),
+                        );
+                    }
+                }
+                "max-age" => {
+                    if let Ok(seconds) = value.parse::<i64>() {
+                        max_age = Some(if seconds <= 0 {
+                            0
+                        } else {
+                            now.saturating_add(seconds as u64)
+                        });
+                    }
+                }
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    // Cookies for a whole top level domain or for unrelated hosts are refused
+                    if !domain_match(&host, &domain) || (!domain.contains('.') && domain != host) {
+                        return;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => {
+                    cookie.same_site = match value.to_ascii_lowercase().as_str() {
+                        "strict" => SameSite::Strict,
+                        "none" => SameSite::None,
+                        _ => SameSite::Lax,
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Max-Age wins over Expires
+        if max_age.is_some() {
+            cookie.expires = max_age;
+        }
+        // Only secure origins may set secure cookies, and cross-site cookies have to be secure
+        let secure_origin = location.scheme() == "https";
+        if (cookie.secure && !secure_origin)
+            || (cookie.same_site == SameSite::None && !cookie.secure)
+        {
+            return;
+        }
+        let existing = self.cookies.iter().position(|other| {
+            other.name == cookie.name && other.domain == cookie.domain && other.path == cookie.path
+        });
+        if let Some(i) = existing {
+            cookie.creation = self.cookies[i].creation;
+            self.cookies.remove(i);
+        }
+        if cookie.expires.is_none_or(|expires| expires > now) {
+            self.cookies.push(cookie);
+        }
+    }
+
+    // The `Cookie` header for a request to `location`, if any cookies go along with it
+    pub fn cookie_header(
+        &mut self,
+        location: &Url,
+        initiator: Initiator,
+        safe_method: bool,
+    ) -> Option<String> {
+        let host = location.host_str()?.to_ascii_lowercase();
+        let now = now();
+        self.cookies
+            .retain(|cookie| cookie.expires.is_none_or(|expires| expires > now));
+        let same_site = match initiator {
+            Initiator::User => true,
+            Initiator::Navigation(from) | Initiator::Subresource(from) => {
+                site(from) == site(location)
+            }
+        };
+        let mut cookies: Vec<&Cookie> = self
+            .cookies
+            .iter()
+            .filter(|cookie| {
+                if cookie.host_only {
+                    cookie.domain == host
+                } else {
+                    domain_match(&host, &cookie.domain)
+                }
+            })
+            .filter(|cookie| path_match(location.path(), &cookie.path))
+            .filter(|cookie| !cookie.secure || location.scheme() == "https")
+            .filter(|cookie| match (cookie.same_site, initiator) {
+                _ if same_site => true,
+                (SameSite::None, _) => true,
+                // Lax cookies still go along when following a link to another site
+                (SameSite::Lax, Initiator::Navigation(_)) => safe_method,
+                _ => false,
+            })
+            .collect();
+        if cookies.is_empty() {
+            return None;
+        }
+        // Longer paths first, then older cookies first
+        cookies.sort_by_key(|cookie| (std::cmp::Reverse(cookie.path.len()), cookie.creation));
+        Some(
+            cookies
+                .iter()
+                .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+                .collect::<Vec<String>>()
+                .join("; "),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(location: &str) -> Url {
+        Url::parse(location).unwrap()
+    }
+
+    fn header(jar: &mut CookieJar, location: &str) -> Option<String> {
+        jar.cookie_header(&url(location), Initiator::User, true)
+    }
+
+    #[test]
+    fn host_only_and_domain_cookies() {
+        let mut jar = CookieJar::default();
+        jar.set_cookie(&url("http://example.com/"), "host=1");
+        jar.set_cookie(&url("http://example.com/"), "domain=2; Domain=.example.com");
+        assert_eq!(
+            header(&mut jar, "http://example.com/"),
+            Some("host=1; domain=2".into())
+        );
+        assert_eq!(
+            header(&mut jar, "http://www.example.com/"),
+            Some("domain=2".into())
+        );
+        assert_eq!(header(&mut jar, "http://notexample.com/"), None);
+        // Other sites and top level domains can't be set from a host
+        jar.set_cookie(&url("http://example.com/"), "other=3; Domain=example.org");
+        jar.set_cookie(&url("http://example.com/"), "tld=4; Domain=com");
+        assert_eq!(header(&mut jar, "http://example.org/"), None);
+        assert_eq!(header(&mut jar, "http://other.com/"), None);
+    }
+
+    #[test]
+    fn path_prefixes() {
+        assert!(path_match("/docs", "/docs"));
+        assert!(path_match("/docs/page", "/docs"));
+        assert!(path_match("/docs/page", "/docs/"));
+        assert!(!path_match("/docsearch", "/docs"));
+        assert!(!path_match("/", "/docs"));
+        assert_eq!(default_path(&url("http://example.com/docs/page")), "/docs");
+        assert_eq!(default_path(&url("http://example.com/page")), "/");
+
+        let mut jar = CookieJar::default();
+        jar.set_cookie(&url("http://example.com/docs/page"), "a=1");
+        assert_eq!(
+            header(&mut jar, "http://example.com/docs/other"),
+            Some("a=1".into())
+        );
+        assert_eq!(header(&mut jar, "http://example.com/docsearch"), None);
+    }
+
+    #[test]
+    fn max_age_deletes() {
+        let mut jar = CookieJar::default();
+        jar.set_cookie(&url("http://example.com/"), "a=1; Max-Age=3600");
+        assert_eq!(header(&mut jar, "http://example.com/"), Some("a=1".into()));
+        jar.set_cookie(&url("http://example.com/"), "a=1; Max-Age=0");
+        assert_eq!(header(&mut jar, "http://example.com/"), None);
+        // Max-Age wins over an Expires in the future
+        jar.set_cookie(
+            &url("http://example.com/"),
+            "b=2; Expires=Fri, 01 Jan 2100 00:00:00 GMT; Max-Age=-1",
+        );
+        assert_eq!(header(&mut jar, "http://example.com/"), None);
+    }
+
+    #[test]
+    fn site_is_last_two_labels() {
+        assert_eq!(
+            site(&url("http://a.b.example.com/")),
+            Some("example.com".into())
+        );
+        assert_eq!(
+            site(&url("http://WWW.Example.com/")),
+            Some("example.com".into())
+        );
+        assert_eq!(
+            site(&url("http://example.com/")),
+            Some("example.com".into())
+        );
+        assert_eq!(site(&url("http://localhost/")), Some("localhost".into()));
+        // Without a public suffix list hosts under the same two label suffix share a site
+        assert_eq!(site(&url("http://a.co.uk/")), site(&url("http://b.co.uk/")));
+        assert_eq!(site(&url("file:///tmp/page.html")), None);
+
+        let mut jar = CookieJar::default();
+        jar.set_cookie(&url("http://example.com/"), "strict=1; SameSite=Strict");
+        let from = url("http://www.example.com/");
+        let header = jar.cookie_header(
+            &url("http://example.com/"),
+            Initiator::Navigation(&from),
+            true,
+        );
+        assert_eq!(header, Some("strict=1".into()));
+        let from = url("http://example.org/");
+        let header = jar.cookie_header(
+            &url("http://example.com/"),
+            Initiator::Navigation(&from),
+            true,
+        );
+        assert_eq!(header, None);
+    }
+}
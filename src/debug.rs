@@ -0,0 +1,264 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+
+use crate::renderer::{draw_text, text_width, DisplayItem, RendererContext};
+use crate::style::Style;
+
+// Layers of the debug overlay, Shift+F12 steps through them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overlay {
+    All,
+    HitRects,
+    Boxes,
+    Spacing,
+    Baselines,
+}
+impl Overlay {
+    pub fn next(self) -> Overlay {
+        match self {
+            Overlay::All => Overlay::HitRects,
+            Overlay::HitRects => Overlay::Boxes,
+            Overlay::Boxes => Overlay::Spacing,
+            Overlay::Spacing => Overlay::Baselines,
+            Overlay::Baselines => Overlay::All,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Overlay::All => "all",
+            Overlay::HitRects => "hit rects",
+            Overlay::Boxes => "boxes",
+            Overlay::Spacing => "margins and padding",
+            Overlay::Baselines => "baselines",
+        }
+    }
+
+    fn includes(self, layer: Overlay) -> bool {
+        self == Overlay::All || self == layer
+    }
+}
+
+// Border box of a block in document coordinates, with its margins, borders and padding in
+// output pixels
+#[derive(Clone, Debug)]
+pub struct LayoutBox {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub margin: [i32; 4],
+    pub border: [u32; 4],
+    pub padding: [u32; 4],
+}
+
+#[derive(Clone, Debug)]
+pub struct DebugOverlays {
+    // Toggled with F12
+    pub shown: bool,
+    pub overlay: Overlay,
+    // Blocks and the baselines of lines (x, y, width) from the last layout
+    pub boxes: Vec<LayoutBox>,
+    pub baselines: Vec<(i32, i32, u32)>,
+    pub layout_time: Duration,
+    // Set while painting, which can't change the rest of the context
+    pub paint_time: Cell<Duration>,
+}
+impl Default for DebugOverlays {
+    fn default() -> DebugOverlays {
+        DebugOverlays {
+            shown: false,
+            overlay: Overlay::All,
+            boxes: Vec::new(),
+            baselines: Vec::new(),
+            layout_time: Duration::ZERO,
+            paint_time: Cell::new(Duration::ZERO),
+        }
+    }
+}
+
+// Fill the rectangles between an outer and an inner box, given by their edges
+fn fill_between(context: &RendererContext, outer: [i32; 4], inner: [i32; 4], color: Color) {
+    let [top, right, bottom, left] = outer;
+    let [inner_top, inner_right, inner_bottom, inner_left] = inner;
+    let bands = [
+        (left, top, right - left, inner_top - top),
+        (left, inner_bottom, right - left, bottom - inner_bottom),
+        (left, inner_top, inner_left - left, inner_bottom - inner_top),
+        (
+            inner_right,
+            inner_top,
+            right - inner_right,
+            inner_bottom - inner_top,
+        ),
+    ];
+    let mut canvas = context.canvas.borrow_mut();
+    canvas.set_draw_color(color);
+    for (x, y, width, height) in bands {
+        if width > 0 && height > 0 {
+            canvas
+                .fill_rect(Rect::new(x, y, width as u32, height as u32))
+                .unwrap();
+        }
+    }
+}
+
+fn paint_spacing(context: &RendererContext, layout_box: &LayoutBox) {
+    let (dx, dy) = context.viewport;
+    let [margin, border, padding] = [
+        layout_box.margin,
+        layout_box.border.map(|b| b as i32),
+        layout_box.padding.map(|p| p as i32),
+    ];
+    let border_box = [
+        layout_box.y + dy,
+        layout_box.x + dx + layout_box.width as i32,
+        layout_box.y + dy + layout_box.height as i32,
+        layout_box.x + dx,
+    ];
+    // Edges move outwards on the top and left and inwards on the bottom and right
+    let inset = |edges: [i32; 4], by: [i32; 4]| {
+        [
+            edges[0] + by[0],
+            edges[1] - by[1],
+            edges[2] - by[2],
+            edges[3] + by[3],
+        ]
+    };
+    let margin_box = inset(border_box, margin.map(|m| -m.max(0)));
+    let padding_box = inset(border_box, border);
+    let content_box = inset(padding_box, padding);
+    fill_between(
+        context,
+        margin_box,
+        border_box,
+        Color::RGBA(246, 178, 107, 96),
+    );
+    fill_between(
+        context,
+        border_box,
+        padding_box,
+        Color::RGBA(255, 229, 153, 128),
+    );
+    fill_between(
+        context,
+        padding_box,
+        content_box,
+        Color::RGBA(147, 196, 125, 96),
+    );
+}
+
+// Draw the layers of the debug overlay and the time the last layout and paint took
+pub fn paint_debug_overlays(context: &RendererContext) {
+    let debug = &context.debug;
+    if !debug.shown {
+        return;
+    }
+    let (dx, dy) = context.viewport;
+    context.canvas.borrow_mut().set_blend_mode(BlendMode::Blend);
+    if debug.overlay.includes(Overlay::Spacing) {
+        for layout_box in &debug.boxes {
+            paint_spacing(context, layout_box);
+        }
+    }
+    {
+        let mut canvas = context.canvas.borrow_mut();
+        if debug.overlay.includes(Overlay::Boxes) {
+            canvas.set_draw_color(Color::RGBA(0, 0, 255, 160));
+            for layout_box in &debug.boxes {
+                canvas
+                    .draw_rect(Rect::new(
+                        layout_box.x + dx,
+                        layout_box.y + dy,
+                        layout_box.width.max(1),
+                        layout_box.height.max(1),
+                    ))
+                    .unwrap();
+            }
+            canvas.set_draw_color(Color::RGBA(0, 160, 160, 160));
+            for item in &context.display_list {
+                if let DisplayItem::Text {
+                    x,
+                    y,
+                    width,
+                    height,
+                    ..
+                }
+                | DisplayItem::Image {
+                    x,
+                    y,
+                    width,
+                    height,
+                    ..
+                }
+                | DisplayItem::Control {
+                    x,
+                    y,
+                    width,
+                    height,
+                    ..
+                } = *item
+                {
+                    canvas
+                        .draw_rect(Rect::new(x + dx, y + dy, width.max(1), height.max(1)))
+                        .unwrap();
+                }
+            }
+        }
+        if debug.overlay.includes(Overlay::Baselines) {
+            canvas.set_draw_color(Color::RGBA(255, 0, 255, 200));
+            for &(x, y, width) in &debug.baselines {
+                canvas
+                    .draw_line((x + dx, y + dy), (x + dx + width as i32, y + dy))
+                    .unwrap();
+            }
+        }
+        if debug.overlay.includes(Overlay::HitRects) {
+            canvas.set_draw_color(Color::RED);
+            for hit_rect in &context.hit_map {
+                canvas
+                    .draw_rect(Rect::new(
+                        hit_rect.x + dx,
+                        hit_rect.y + dy,
+                        hit_rect.width.max(1),
+                        hit_rect.height.max(1),
+                    ))
+                    .unwrap();
+            }
+        }
+    }
+    context.canvas.borrow_mut().set_blend_mode(BlendMode::None);
+    // Timings in a strip at the top right
+    let text = format!(
+        "{}  layout {:.1} ms  paint {:.1} ms",
+        debug.overlay.name(),
+        debug.layout_time.as_secs_f32() * 1000.0,
+        debug.paint_time.get().as_secs_f32() * 1000.0
+    );
+    let screen_width = context.canvas.borrow().output_size().unwrap().0;
+    let sf = context.scaling_factor;
+    let font_size = 14 * sf;
+    let width = (text_width(&context.font.borrow(), &text, font_size) + 12 * sf).min(screen_width);
+    let height = font_size + 8 * sf;
+    let left = (screen_width - width) as i32;
+    {
+        let mut canvas = context.canvas.borrow_mut();
+        canvas.set_draw_color(context.color_scheme.background);
+        canvas.fill_rect(Rect::new(left, 0, width, height)).unwrap();
+        canvas.set_draw_color(context.color_scheme.text);
+        canvas.draw_rect(Rect::new(left, 0, width, height)).unwrap();
+    }
+    draw_text(
+        context,
+        &text,
+        &Style::default(),
+        font_size,
+        context.color_scheme.text,
+        left + 6 * sf as i32,
+        4 * sf as i32,
+    );
+}
@@ -0,0 +1,185 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+
+// Label of the `charset` parameter in a Content-Type header or meta element
+fn charset_parameter(content_type: &str) -> Option<&str> {
+    let lower = content_type.to_ascii_lowercase();
+    let start = lower.find("charset")? + "charset".len();
+    let rest = content_type[start..]
+        .trim_start()
+        .strip_prefix('=')?
+        .trim_start();
+    let rest = rest.trim_start_matches(['"', '\'']);
+    let end = rest
+        .find(|c: char| c == ';' || c == '"' || c == '\'' || c.is_whitespace())
+        .unwrap_or(rest.len());
+    Some(&rest[..end]).filter(|label| !label.is_empty())
+}
+
+// Encoding given in a meta element, where UTF-16 means the document was transcoded to UTF-8
+fn meta_encoding(label: &str) -> Option<&'static Encoding> {
+    let encoding = Encoding::for_label(label.as_bytes())?;
+    Some(if encoding == UTF_16BE || encoding == UTF_16LE {
+        UTF_8
+    } else if encoding == X_USER_DEFINED {
+        WINDOWS_1252
+    } else {
+        encoding
+    })
+}
+
+// Look for `<meta charset>` or `<meta http-equiv="content-type">` in the first kilobyte,
+// a simplified version of the prescan of the HTML standard
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]).to_ascii_lowercase();
+    let mut rest = head.as_str();
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..end];
+        rest = &rest[end..];
+        if !tag.starts_with("<meta")
+            || !tag[5..].starts_with(|c: char| c.is_whitespace() || c == '/')
+        {
+            continue;
+        }
+        let attributes = parse_attributes(&tag[5..]);
+        let attribute = |name: &str| {
+            attributes
+                .iter()
+                .find(|(attribute, _)| attribute == name)
+                .map(|(_, value)| value.as_str())
+        };
+        if let Some(encoding) = attribute("charset").and_then(meta_encoding) {
+            return Some(encoding);
+        }
+        if attribute("http-equiv") == Some("content-type") {
+            if let Some(encoding) = attribute("content")
+                .and_then(charset_parameter)
+                .and_then(meta_encoding)
+            {
+                return Some(encoding);
+            }
+        }
+    }
+    None
+}
+
+// Names and values of the attributes in the inside of a tag
+fn parse_attributes(mut tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    loop {
+        tag = tag.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if tag.is_empty() {
+            return attributes;
+        }
+        let end = tag
+            .find(|c: char| c == '=' || c.is_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        let name = tag[..end].to_string();
+        tag = tag[end..].trim_start();
+        let value = match tag.strip_prefix('=') {
+            Some(value) => {
+                let value = value.trim_start();
+                match value.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let value = &value[1..];
+                        let end = value.find(quote).unwrap_or(value.len());
+                        tag = value.get(end + 1..).unwrap_or("");
+                        value[..end].to_string()
+                    }
+                    _ => {
+                        let end = value
+                            .find(|c: char| c.is_whitespace())
+                            .unwrap_or(value.len());
+                        tag = &value[end..];
+                        value[..end].to_string()
+                    }
+                }
+            }
+            None => String::new(),
+        };
+        attributes.push((name, value));
+    }
+}
+
+// Decode a document, sniffing its encoding from a byte order mark, then the user's override,
+// then the charset of the Content-Type header and then meta elements. Documents that declare
+// nothing are read as UTF-8 when they are valid UTF-8 and as windows-1252 otherwise.
+pub fn decode(
+    bytes: &[u8],
+    content_type: Option<&str>,
+    user_encoding: Option<&'static Encoding>,
+) -> String {
+    let encoding = Encoding::for_bom(bytes)
+        .map(|(encoding, _)| encoding)
+        .or(user_encoding)
+        .or_else(|| {
+            content_type
+                .and_then(charset_parameter)
+                .and_then(|label| Encoding::for_label(label.as_bytes()))
+        })
+        .or_else(|| prescan(bytes))
+        .unwrap_or_else(|| {
+            if std::str::from_utf8(bytes).is_ok() {
+                UTF_8
+            } else {
+                WINDOWS_1252
+            }
+        });
+    let (text, _, malformed) = encoding.decode(bytes);
+    if malformed {
+        println!("Document isn't valid {}", encoding.name());
+    }
+    text.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bom_overrides_meta() {
+        let bytes = b"\xEF\xBB\xBF<meta charset=windows-1252>\xC3\xA9";
+        assert_eq!(
+            decode(bytes, None, None),
+            "<meta charset=windows-1252>\u{e9}"
+        );
+        let bytes = b"<meta charset=windows-1252>\xC3\xA9";
+        assert_eq!(
+            decode(bytes, None, None),
+            "<meta charset=windows-1252>\u{c3}\u{a9}"
+        );
+    }
+
+    #[test]
+    fn content_type_charsets() {
+        assert_eq!(
+            charset_parameter("text/html; charset=ISO-8859-2"),
+            Some("ISO-8859-2")
+        );
+        assert_eq!(
+            charset_parameter("text/html; charset=\"utf-8\""),
+            Some("utf-8")
+        );
+        assert_eq!(charset_parameter("text/html"), None);
+        let meta = b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=koi8-r\">";
+        assert_eq!(prescan(meta), Some(encoding_rs::KOI8_R));
+        // The header wins over meta elements
+        let bytes = b"<meta charset=utf-8>\xE9";
+        assert_eq!(
+            decode(bytes, Some("text/html; charset=windows-1252"), None),
+            "<meta charset=utf-8>\u{e9}"
+        );
+    }
+
+    #[test]
+    fn fallback_without_label() {
+        assert_eq!(prescan(b"<!-- <meta charset=koi8-r> --><p>text"), None);
+        assert_eq!(decode("<p>\u{e9}".as_bytes(), None, None), "<p>\u{e9}");
+        assert_eq!(decode(b"<p>\xE9", None, None), "<p>\u{e9}");
+    }
+}
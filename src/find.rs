@@ -0,0 +1,138 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use crate::renderer::{
+    clamp_viewport, draw_text, text_color, text_width, DisplayItem, RendererContext,
+};
+use crate::style::Style;
+
+// Find-in-page state while the search bar is open
+#[derive(Clone, Default)]
+pub struct FindBar {
+    pub query: String,
+    // Item in the display list and byte range of every match, in document order
+    pub matches: Vec<(usize, usize, usize)>,
+    pub current: usize,
+}
+
+// Search all text items for the query, ignoring ASCII case
+pub fn update_matches(context: &mut RendererContext) {
+    let find = match context.find.as_mut() {
+        Some(find) => find,
+        None => return,
+    };
+    find.matches.clear();
+    find.current = 0;
+    let query = find.query.to_ascii_lowercase();
+    if query.is_empty() {
+        return;
+    }
+    for (i, item) in context.display_list.iter().enumerate() {
+        if let DisplayItem::Text { text, .. } = item {
+            let text = text.to_ascii_lowercase();
+            find.matches.extend(
+                text.match_indices(&query)
+                    .map(|(start, found)| (i, start, start + found.len())),
+            );
+        }
+    }
+}
+
+// Move to the next or previous match, wrapping around at either end
+pub fn cycle_match(context: &mut RendererContext, backwards: bool) {
+    if let Some(find) = context.find.as_mut() {
+        let count = find.matches.len();
+        if count > 0 {
+            find.current = if backwards {
+                (find.current + count - 1) % count
+            } else {
+                (find.current + 1) % count
+            };
+        }
+    }
+}
+
+// Scroll the current match into view unless it is already visible
+pub fn scroll_to_match(context: &mut RendererContext) {
+    let item = match context.find.as_ref() {
+        Some(find) if !find.matches.is_empty() => find.matches[find.current].0,
+        _ => return,
+    };
+    let (y, height) = match context.display_list[item] {
+        DisplayItem::Text { y, height, .. } => (y, height as i32),
+        _ => return,
+    };
+    let screen_height = context.canvas.borrow().output_size().unwrap().1 as i32;
+    let top = y + context.viewport.1;
+    if top < 0 || top + height > screen_height - bar_height(context) as i32 {
+        context.viewport.1 = screen_height / 3 - y;
+        clamp_viewport(context);
+    }
+}
+
+pub fn bar_height(context: &RendererContext) -> u32 {
+    28 * context.scaling_factor
+}
+
+// Draw the search bar with the query and match counter over the bottom of the canvas
+pub fn paint_find_bar(context: &RendererContext, find: &FindBar) {
+    let (screen_width, screen_height) = context.canvas.borrow().output_size().unwrap();
+    let sf = context.scaling_factor;
+    let height = bar_height(context);
+    let top = (screen_height - height) as i32;
+    {
+        let mut canvas = context.canvas.borrow_mut();
+        canvas.set_draw_color(context.color_scheme.background);
+        canvas
+            .fill_rect(Rect::new(0, top, screen_width, height))
+            .unwrap();
+        canvas.set_draw_color(context.color_scheme.text);
+        canvas
+            .fill_rect(Rect::new(0, top, screen_width, sf))
+            .unwrap();
+    }
+    let style = Style::default();
+    let color = text_color(context, &style);
+    let y = top + (6 * sf) as i32;
+    let x = 8 * sf as i32;
+    let label = format!("Find: {}", find.query);
+    let width = draw_text(context, &label, &style, 16 * sf, color, x, y);
+    // Text cursor after the query
+    context.canvas.borrow_mut().set_draw_color(color);
+    context
+        .canvas
+        .borrow_mut()
+        .fill_rect(Rect::new(x + width as i32 + 1, y, sf, 16 * sf))
+        .unwrap();
+    let counter = match find.matches.len() {
+        _ if find.query.is_empty() => String::new(),
+        0 => String::from("No matches"),
+        count => format!("{} of {}", find.current + 1, count),
+    };
+    if !counter.is_empty() {
+        let counter_width = text_width(&context.font.borrow(), &counter, 16 * sf);
+        let x = screen_width.saturating_sub(counter_width + 8 * sf) as i32;
+        draw_text(context, &counter, &style, 16 * sf, color, x, y);
+    }
+}
+
+// Highlight colour for the match ranges of the text item at `index`
+pub fn match_ranges(context: &RendererContext, index: usize) -> Vec<(usize, usize, Color)> {
+    match context.find.as_ref() {
+        Some(find) => find
+            .matches
+            .iter()
+            .enumerate()
+            .filter(|(_, (item, _, _))| *item == index)
+            .map(|(i, (_, from, to))| {
+                let color = if i == find.current {
+                    context.color_scheme.current_find_match
+                } else {
+                    context.color_scheme.find_match
+                };
+                (*from, *to, color)
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
@@ -0,0 +1,99 @@
+use sdl2::rect::Rect;
+
+use crate::renderer::{clamp_viewport, RendererContext};
+
+// An element that can be reached with Tab, in document order
+#[derive(Clone, Debug)]
+pub enum Focusable {
+    Link { href: String },
+    // Index of a form control in the renderer context
+    Control { index: usize },
+}
+
+// Move focus to the next or previous focusable element, wrapping around at either end
+pub fn cycle_focus(context: &mut RendererContext, backwards: bool) {
+    let count = context.focusables.len();
+    if count == 0 {
+        return;
+    }
+    context.focus = Some(match context.focus {
+        None if backwards => count - 1,
+        None => 0,
+        Some(focus) if backwards => (focus + count - 1) % count,
+        Some(focus) => (focus + 1) % count,
+    });
+}
+
+// Bounding box of everything laid out for the focused element, in document coordinates
+fn focus_bounds(context: &RendererContext) -> Option<Rect> {
+    let focus = context.focus?;
+    context
+        .hit_map
+        .iter()
+        .filter(|hit_rect| hit_rect.focus == Some(focus))
+        .map(|hit_rect| Rect::new(hit_rect.x, hit_rect.y, hit_rect.width, hit_rect.height))
+        .reduce(|a, b| a.union(b))
+}
+
+// Scroll just far enough for the focused element to be visible
+pub fn scroll_to_focus(context: &mut RendererContext) {
+    let bounds = match focus_bounds(context) {
+        Some(bounds) => bounds,
+        None => return,
+    };
+    let (screen_width, screen_height) = context.canvas.borrow().output_size().unwrap();
+    let margin = 16 * context.scaling_factor as i32;
+    let fit = |viewport: i32, start: i32, length: u32, screen: u32| {
+        let (start, end) = (start + viewport, start + viewport + length as i32);
+        if start < 0 || length as i32 > screen as i32 - 2 * margin {
+            viewport - start + margin
+        } else if end > screen as i32 {
+            viewport - (end - screen as i32) - margin
+        } else {
+            viewport
+        }
+    };
+    context.viewport = (
+        fit(context.viewport.0, bounds.x(), bounds.width(), screen_width),
+        fit(
+            context.viewport.1,
+            bounds.y(),
+            bounds.height(),
+            screen_height,
+        ),
+    );
+    clamp_viewport(context);
+}
+
+// Outline every part of the focused element
+pub fn paint_focus_ring(context: &RendererContext) {
+    let focus = match context.focus {
+        Some(focus) => focus,
+        None => return,
+    };
+    let sf = context.scaling_factor as i32;
+    let mut canvas = context.canvas.borrow_mut();
+    canvas.set_draw_color(context.color_scheme.focus_ring);
+    for hit_rect in context
+        .hit_map
+        .iter()
+        .filter(|hit_rect| hit_rect.focus == Some(focus))
+    {
+        let x = hit_rect.x + context.viewport.0 - 2 * sf;
+        let y = hit_rect.y + context.viewport.1 - sf;
+        let (width, height) = (
+            hit_rect.width + 4 * sf as u32,
+            hit_rect.height + 2 * sf as u32,
+        );
+        for i in 0..sf.max(1) {
+            canvas
+                .draw_rect(Rect::new(
+                    x + i,
+                    y + i,
+                    width - 2 * i as u32,
+                    height - 2 * i as u32,
+                ))
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,550 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rcdom::{Handle, NodeData};
+
+use sdl2::gfx::primitives::DrawRenderer;
+use sdl2::rect::Rect;
+
+use url::form_urlencoded;
+use url::Url;
+
+use crate::focus::Focusable;
+use crate::renderer::{
+    draw_text, font_for, place_inline_box, scaled, text_color, text_width, RendererContext,
+};
+use crate::style::Style;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlKind {
+    Text,
+    Password,
+    TextArea,
+    Checkbox,
+    Radio,
+    Select,
+    Submit,
+    Reset,
+    Button,
+    Hidden,
+}
+
+// State of an `<input>`, `<textarea>`, `<select>` or `<button>`, kept across layouts
+#[derive(Clone, Debug)]
+pub struct Control {
+    pub kind: ControlKind,
+    pub name: String,
+    // Index of the form the control belongs to in the renderer context
+    pub form: Option<usize>,
+    // Typed text, the value submitted for a checked box, or the label of a button
+    pub value: String,
+    pub checked: bool,
+    // Value and label of each option of a select, and the selected one
+    pub options: Vec<(String, String)>,
+    pub selected: usize,
+    pub disabled: bool,
+    // Value, checkedness and selection the control started with, restored by a reset button
+    pub initial: (String, bool, usize),
+    // Width in characters, and rows of a text area
+    pub size: (u32, u32),
+}
+
+#[derive(Clone, Debug)]
+pub struct Form {
+    pub action: String,
+    pub method: String,
+    pub enctype: String,
+}
+
+// A request to send when a form is submitted
+#[derive(Clone, Debug)]
+pub struct Submission {
+    pub location: Url,
+    // Content type and body of a POST request
+    pub post: Option<(String, Vec<u8>)>,
+}
+
+fn attribute(handle: &Handle, attribute: &str) -> Option<String> {
+    match handle.data {
+        NodeData::Element { ref attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|a| &a.name.local == attribute)
+            .map(|a| a.value.to_string()),
+        _ => None,
+    }
+}
+
+fn text_content(handle: &Handle) -> String {
+    match handle.data {
+        NodeData::Text { ref contents } => contents.borrow().to_string(),
+        _ => handle.children.borrow().iter().map(text_content).collect(),
+    }
+}
+
+pub fn form_for(handle: &Handle) -> Form {
+    Form {
+        action: attribute(handle, "action").unwrap_or_default(),
+        method: attribute(handle, "method")
+            .unwrap_or_default()
+            .to_ascii_lowercase(),
+        enctype: attribute(handle, "enctype")
+            .unwrap_or_default()
+            .to_ascii_lowercase(),
+    }
+}
+
+// The control an element stands for, with the state given by its attributes
+pub fn control_for(handle: &Handle, form: Option<usize>) -> Option<Control> {
+    let name = match handle.data {
+        NodeData::Element { ref name, .. } => name.local.to_string(),
+        _ => return None,
+    };
+    let input_type = attribute(handle, "type")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let value = attribute(handle, "value");
+    let (kind, value) = match (name.as_str(), input_type.as_str()) {
+        ("input", "password") => (ControlKind::Password, value.unwrap_or_default()),
+        ("input", "checkbox") => (ControlKind::Checkbox, value.unwrap_or_else(|| "on".into())),
+        ("input", "radio") => (ControlKind::Radio, value.unwrap_or_else(|| "on".into())),
+        ("input", "submit") => (
+            ControlKind::Submit,
+            value.unwrap_or_else(|| "Submit".into()),
+        ),
+        ("input", "reset") => (ControlKind::Reset, value.unwrap_or_else(|| "Reset".into())),
+        ("input", "button") => (ControlKind::Button, value.unwrap_or_default()),
+        ("input", "hidden") => (ControlKind::Hidden, value.unwrap_or_default()),
+        // File uploads and image buttons aren't supported
+        ("input", "file" | "image") => return None,
+        ("input", _) => (ControlKind::Text, value.unwrap_or_default()),
+        // The parser already drops a newline right after the start tag
+        ("textarea", _) => (ControlKind::TextArea, text_content(handle)),
+        ("select", _) => (ControlKind::Select, String::new()),
+        ("button", "reset") => (ControlKind::Reset, text_content(handle).trim().to_string()),
+        ("button", "button") => (ControlKind::Button, text_content(handle).trim().to_string()),
+        ("button", _) => (ControlKind::Submit, text_content(handle).trim().to_string()),
+        _ => return None,
+    };
+    let mut options = Vec::new();
+    let mut selected = 0;
+    if kind == ControlKind::Select {
+        collect_options(handle, &mut options, &mut selected);
+    }
+    let number = |attribute_name: &str, default: u32| {
+        attribute(handle, attribute_name)
+            .and_then(|value| value.trim().parse().ok())
+            .filter(|&value| value > 0)
+            .unwrap_or(default)
+    };
+    let size = match kind {
+        ControlKind::TextArea => (number("cols", 20), number("rows", 2)),
+        _ => (number("size", 20), 1),
+    };
+    let checked = attribute(handle, "checked").is_some();
+    Some(Control {
+        kind,
+        name: attribute(handle, "name").unwrap_or_default(),
+        form,
+        initial: (value.clone(), checked, selected),
+        value,
+        checked,
+        options,
+        selected,
+        disabled: attribute(handle, "disabled").is_some(),
+        size,
+    })
+}
+
+fn collect_options(handle: &Handle, options: &mut Vec<(String, String)>, selected: &mut usize) {
+    for child in handle.children.borrow().iter() {
+        if let NodeData::Element { ref name, .. } = child.data {
+            match &*name.local {
+                "option" => {
+                    let label = text_content(child).trim().to_string();
+                    if attribute(child, "selected").is_some() {
+                        *selected = options.len();
+                    }
+                    options.push((
+                        attribute(child, "value").unwrap_or_else(|| label.clone()),
+                        label,
+                    ));
+                }
+                "optgroup" => collect_options(child, options, selected),
+                _ => {}
+            }
+        }
+    }
+}
+
+// Whether typing goes into the control
+pub fn edits_text(control: &Control) -> bool {
+    matches!(
+        control.kind,
+        ControlKind::Text | ControlKind::Password | ControlKind::TextArea
+    )
+}
+
+// The control with keyboard focus, if it is a form control
+pub fn focused_control(context: &RendererContext) -> Option<usize> {
+    match context.focusables.get(context.focus?)? {
+        Focusable::Control { index } => Some(*index),
+        _ => None,
+    }
+}
+
+// Whether a text control has keyboard focus and takes text input
+pub fn editing_text(context: &RendererContext) -> bool {
+    focused_control(context).is_some_and(|index| edits_text(&context.controls[index]))
+}
+
+fn control_style(control: &Control) -> Style {
+    Style {
+        monospace: control.kind == ControlKind::TextArea,
+        font_size: 14,
+        ..Style::default()
+    }
+}
+
+fn label(control: &Control) -> &str {
+    match control.kind {
+        ControlKind::Select => control
+            .options
+            .get(control.selected)
+            .map_or("", |(_, label)| label),
+        _ => &control.value,
+    }
+}
+
+// Size of the control's box in output pixels
+fn control_size(context: &RendererContext, control: &Control) -> (u32, u32) {
+    let style = control_style(control);
+    let font = font_for(context, &style);
+    let font = font.borrow();
+    let font_size = scaled(context, style.font_size);
+    let padding = scaled(context, 4);
+    let line = font_size + 2 * padding;
+    let char_width = text_width(&font, "0", font_size);
+    match control.kind {
+        ControlKind::Checkbox | ControlKind::Radio => (scaled(context, 13), scaled(context, 13)),
+        ControlKind::Text | ControlKind::Password => {
+            (control.size.0 * char_width + 2 * padding, line)
+        }
+        ControlKind::TextArea => (
+            control.size.0 * char_width + 2 * padding,
+            control.size.1 * font_size + 2 * padding,
+        ),
+        ControlKind::Select => {
+            let widest = control
+                .options
+                .iter()
+                .map(|(_, label)| text_width(&font, label, font_size))
+                .max()
+                .unwrap_or(0);
+            (widest + 2 * padding + font_size, line)
+        }
+        ControlKind::Submit | ControlKind::Reset | ControlKind::Button => (
+            text_width(&font, &control.value, font_size) + 4 * padding,
+            line,
+        ),
+        ControlKind::Hidden => (0, 0),
+    }
+}
+
+// Register a control found while laying out the document and place its box on the line.
+// Controls are matched up by their position in the document, so relayouts keep what was typed.
+pub fn add_control(context: &mut RendererContext, control: Control) {
+    let index = context.control_count;
+    context.control_count += 1;
+    match context.controls.get(index) {
+        Some(previous) if previous.kind == control.kind && previous.name == control.name => {}
+        _ => {
+            context.controls.truncate(index);
+            context.controls.push(control);
+        }
+    }
+    let control = &context.controls[index];
+    if control.kind == ControlKind::Hidden {
+        return;
+    }
+    let focus = if control.disabled {
+        None
+    } else {
+        context.focusables.push(Focusable::Control { index });
+        Some(context.focusables.len() - 1)
+    };
+    let (width, height) = control_size(context, control);
+    place_inline_box(context, width, height, index, focus);
+}
+
+// Draw a control's box at screen coordinates
+pub fn paint_control(
+    context: &RendererContext,
+    index: usize,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) {
+    let control = &context.controls[index];
+    let style = control_style(control);
+    let color = text_color(context, &style);
+    let scheme = &context.color_scheme;
+    let sf = context.scaling_factor as i32;
+    let font_size = scaled(context, style.font_size);
+    let padding = scaled(context, 4) as i32;
+    let rect = Rect::new(x, y, width, height);
+    let focused = focused_control(context) == Some(index);
+    match control.kind {
+        ControlKind::Radio => {
+            let canvas = context.canvas.borrow();
+            let (cx, cy, r) = (
+                (x + width as i32 / 2) as i16,
+                (y + height as i32 / 2) as i16,
+                (width / 2) as i16,
+            );
+            canvas.filled_circle(cx, cy, r, scheme.background).unwrap();
+            canvas.circle(cx, cy, r, color).unwrap();
+            if control.checked {
+                canvas.filled_circle(cx, cy, r / 2, color).unwrap();
+            }
+            return;
+        }
+        ControlKind::Checkbox => {
+            let mut canvas = context.canvas.borrow_mut();
+            canvas.set_draw_color(scheme.background);
+            canvas.fill_rect(rect).unwrap();
+            canvas.set_draw_color(color);
+            canvas.draw_rect(rect).unwrap();
+            if control.checked {
+                let inset = 3 * sf;
+                canvas
+                    .fill_rect(Rect::new(
+                        x + inset,
+                        y + inset,
+                        width.saturating_sub(2 * inset as u32),
+                        height.saturating_sub(2 * inset as u32),
+                    ))
+                    .unwrap();
+            }
+            return;
+        }
+        _ => {}
+    }
+    let button = matches!(
+        control.kind,
+        ControlKind::Submit | ControlKind::Reset | ControlKind::Button
+    );
+    {
+        let mut canvas = context.canvas.borrow_mut();
+        canvas.set_draw_color(if button {
+            scheme.scrollbar
+        } else {
+            scheme.background
+        });
+        canvas.fill_rect(rect).unwrap();
+        canvas.set_draw_color(color);
+        canvas.draw_rect(rect).unwrap();
+        canvas.set_clip_rect(Rect::new(
+            x + padding,
+            y,
+            width.saturating_sub(2 * padding as u32),
+            height,
+        ));
+    }
+    let text_x = x + if button { 2 * padding } else { padding };
+    let mut caret = (text_x, y + padding);
+    match control.kind {
+        ControlKind::TextArea => {
+            for (i, line) in control.value.split('\n').enumerate() {
+                let line_y = y + padding + i as i32 * font_size as i32;
+                let line_width = draw_text(context, line, &style, font_size, color, text_x, line_y);
+                caret = (text_x + line_width as i32, line_y);
+            }
+        }
+        ControlKind::Password => {
+            let bullets = "*".repeat(control.value.chars().count());
+            let text_width =
+                draw_text(context, &bullets, &style, font_size, color, text_x, caret.1);
+            caret.0 += text_width as i32;
+        }
+        _ => {
+            let text_width = draw_text(
+                context,
+                label(control),
+                &style,
+                font_size,
+                color,
+                text_x,
+                caret.1,
+            );
+            caret.0 += text_width as i32;
+        }
+    }
+    let mut canvas = context.canvas.borrow_mut();
+    canvas.set_clip_rect(None);
+    if focused && edits_text(control) {
+        canvas.set_draw_color(color);
+        canvas
+            .fill_rect(Rect::new(caret.0 + 1, caret.1, sf as u32, font_size))
+            .unwrap();
+    }
+    if control.kind == ControlKind::Select {
+        // Arrow pointing down at the right end of the box
+        let (cx, cy, r) = (
+            x + width as i32 - padding - font_size as i32 / 2,
+            y + height as i32 / 2,
+            font_size as i32 / 4,
+        );
+        canvas
+            .filled_trigon(
+                (cx - r) as i16,
+                (cy - r / 2) as i16,
+                (cx + r) as i16,
+                (cy - r / 2) as i16,
+                cx as i16,
+                (cy + r / 2) as i16,
+                color,
+            )
+            .unwrap();
+    }
+}
+
+// Respond to a click, or Space and Enter, on a control; returns the submission it triggers
+pub fn activate(context: &mut RendererContext, index: usize) -> Option<Submission> {
+    let control = &mut context.controls[index];
+    match control.kind {
+        ControlKind::Checkbox => control.checked = !control.checked,
+        ControlKind::Radio => {
+            let (name, form) = (control.name.clone(), control.form);
+            for (i, other) in context.controls.iter_mut().enumerate() {
+                if i == index
+                    || (other.kind == ControlKind::Radio
+                        && !name.is_empty()
+                        && other.name == name
+                        && other.form == form)
+                {
+                    other.checked = i == index;
+                }
+            }
+        }
+        ControlKind::Select => {
+            control.selected = (control.selected + 1) % control.options.len().max(1);
+        }
+        ControlKind::Submit => {
+            let form = control.form?;
+            return submit(context, form, Some(index));
+        }
+        ControlKind::Reset => {
+            if let Some(form) = control.form {
+                reset(context, form);
+            }
+        }
+        _ => {}
+    }
+    None
+}
+
+// Choose the previous (negative) or next (positive) option of a select
+pub fn select_option(context: &mut RendererContext, index: usize, delta: i32) {
+    let control = &mut context.controls[index];
+    if control.kind == ControlKind::Select && !control.options.is_empty() {
+        control.selected =
+            (control.selected as i32 + delta).clamp(0, control.options.len() as i32 - 1) as usize;
+    }
+}
+
+pub fn reset(context: &mut RendererContext, form: usize) {
+    for control in context
+        .controls
+        .iter_mut()
+        .filter(|control| control.form == Some(form))
+    {
+        let (value, checked, selected) = control.initial.clone();
+        control.value = value;
+        control.checked = checked;
+        control.selected = selected;
+    }
+}
+
+// Build the request for submitting a form, optionally by one of its submit buttons
+pub fn submit(
+    context: &RendererContext,
+    form: usize,
+    submitter: Option<usize>,
+) -> Option<Submission> {
+    let entries: Vec<(String, String)> = context
+        .controls
+        .iter()
+        .enumerate()
+        .filter(|(_, control)| {
+            control.form == Some(form) && !control.disabled && !control.name.is_empty()
+        })
+        .filter_map(|(i, control)| {
+            let value = match control.kind {
+                ControlKind::Text
+                | ControlKind::Password
+                | ControlKind::TextArea
+                | ControlKind::Hidden => Some(control.value.clone()),
+                ControlKind::Checkbox | ControlKind::Radio if control.checked => {
+                    Some(control.value.clone())
+                }
+                ControlKind::Select => control
+                    .options
+                    .get(control.selected)
+                    .map(|(value, _)| value.clone()),
+                ControlKind::Submit if Some(i) == submitter => Some(control.value.clone()),
+                _ => None,
+            };
+            value.map(|value| (control.name.clone(), value))
+        })
+        .collect();
+    let form = &context.forms[form];
+    let mut location = context.location.join(&form.action).ok()?;
+    let urlencoded = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(&entries)
+        .finish();
+    if form.method != "post" {
+        location.set_query(Some(&urlencoded));
+        return Some(Submission {
+            location,
+            post: None,
+        });
+    }
+    let post = if form.enctype == "multipart/form-data" {
+        let boundary = format!(
+            "----opilio{:x}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_nanos())
+                .unwrap_or_default()
+        );
+        let escape = |name: &str| {
+            name.replace('"', "%22")
+                .replace('\r', "%0D")
+                .replace('\n', "%0A")
+        };
+        let mut body = String::new();
+        for (name, value) in &entries {
+            body.push_str(&format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary,
+                escape(name),
+                value
+            ));
+        }
+        body.push_str(&format!("--{}--\r\n", boundary));
+        (
+            format!("multipart/form-data; boundary={}", boundary),
+            body.into_bytes(),
+        )
+    } else {
+        (
+            String::from("application/x-www-form-urlencoded"),
+            urlencoded.into_bytes(),
+        )
+    };
+    Some(Submission {
+        location,
+        post: Some(post),
+    })
+}
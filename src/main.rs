@@ -3,80 +3,133 @@ extern crate html5ever;
 extern crate markup5ever_rcdom as rcdom;
 extern crate sdl2;
 
-use crate::colorscheme::DefaultColorSchemes;
+use crate::colorscheme::{load_theme, theme_names};
+use crate::config::Config;
+use crate::cookies::{CookieJar, Initiator};
+use crate::debug::{DebugOverlays, Overlay};
+use crate::encoding::decode;
+use crate::find::{cycle_match, scroll_to_match, update_matches, FindBar};
+use crate::focus::{cycle_focus, scroll_to_focus, Focusable};
+use crate::forms::{
+    activate, editing_text, edits_text, focused_control, select_option, submit, ControlKind,
+    Submission,
+};
+use crate::navigation::{
+    load_document, location_from_arg, parse_html, same_document, scroll_to_fragment, History,
+};
 use crate::renderer::*;
+use crate::scrollbar::{drag_thumb, page_towards, scrollbar_at, thumb_offset, Axis};
+use crate::selection::{paragraph_at, position_at, selected_text, word_at};
+use crate::style::Style;
+use crate::visited::VisitedLinks;
 
 use std::cell::RefCell;
 use std::collections::HashMap;
 
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, Read};
 
+use std::path::PathBuf;
 use std::rc::Rc;
 
+use std::env;
 use std::str::FromStr;
-use std::{env, fs};
+use std::time::Instant;
 
-use html5ever::parse_document;
-use html5ever::tendril::TendrilSink;
-
-use rcdom::RcDom;
 use sdl2::event::{Event, WindowEvent};
 
-use sdl2::keyboard::Keycode;
-use sdl2::mouse::MouseButton;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::{Cursor, MouseButton, MouseWheelDirection, SystemCursor};
+
+use encoding_rs::Encoding;
+
+use url::Url;
 
 use std::default::Default;
 
 use clap::Parser;
 use std::string::String;
 
+mod alignment;
+mod bidi;
 mod colorscheme;
+mod config;
+mod cookies;
+mod debug;
+mod encoding;
+mod find;
+mod focus;
+mod forms;
+mod navigation;
 mod renderer;
+mod scrollbar;
+mod selection;
+mod shaping;
+mod status;
+mod style;
+mod visited;
 
-static SCREEN_WIDTH: u32 = 800;
-static SCREEN_HEIGHT: u32 = 600;
-static SCROLL_SPEED: i32 = 12;
-static DRAW_HITRECTS: bool = false;
 // static BG_COLOR: Color = Color::WHITE;
 // static FG_COLOR: Color = Color::BLACK;
 
-// handle the annoying Rect i32
-macro_rules! rect(
-    ($x:expr, $y:expr, $w:expr, $h:expr) => (
-        Rect::new($x as i32, $y as i32, $w as u32, $h as u32)
-    )
-);
-
-// Scale fonts to a reasonable size when they're too big (though they might look less smooth)
-fn get_centered_rect(rect_width: u32, rect_height: u32, cons_width: u32, cons_height: u32) -> Rect {
-    let wr = rect_width as f32 / cons_width as f32;
-    let hr = rect_height as f32 / cons_height as f32;
-
-    let (w, h) = if wr > 1f32 || hr > 1f32 {
-        if wr > hr {
-            println!("Scaling down! The text will look worse!");
-            let h = (rect_height as f32 / wr) as i32;
-            (cons_width as i32, h)
-        } else {
-            println!("Scaling down! The text will look worse!");
-            let w = (rect_width as f32 / hr) as i32;
-            (w, cons_height as i32)
-        }
-    } else {
-        (rect_width as i32, rect_height as i32)
-    };
-
-    let cx = (SCREEN_WIDTH as i32 - w) / 2;
-    let cy = (SCREEN_HEIGHT as i32 - h) / 2;
-    rect!(cx, cy, w, h)
-}
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
-    #[clap(short, long, arg_enum, default_value = "standard")]
-    color_theme: DefaultColorSchemes,
+    /// Colour theme, built in or from a TOML file in the themes directory of the config directory,
+    /// or `auto` to follow the light or dark preference of the desktop
+    #[clap(short, long)]
+    color_theme: Option<String>,
+
+    /// List the available colour themes and exit
+    #[clap(long)]
+    list_themes: bool,
+
+    /// Ignore the colours of pages and draw them in the colours of the theme
+    #[clap(long)]
+    forced_colors: bool,
+
+    /// Read settings from this file instead of config.toml in the config directory
+    #[clap(long)]
+    config: Option<String>,
+
+    /// Initial size of the window
+    #[clap(long)]
+    width: Option<u32>,
+    #[clap(long)]
+    height: Option<u32>,
+
+    /// Font files to use for text and for monospace text
+    #[clap(long)]
+    font: Option<String>,
+    #[clap(long)]
+    mono_font: Option<String>,
+
+    /// Size of body text in CSS pixels
+    #[clap(long)]
+    font_size: Option<u32>,
+
+    /// Distance scrolled by a mouse wheel step in CSS pixels
+    #[clap(long)]
+    scroll_speed: Option<i32>,
+
+    /// Keep fetched images in this directory between runs, which is off by default
+    #[clap(long)]
+    cache_dir: Option<String>,
+
+    /// Start with the areas that react to the pointer outlined (F12 toggles the debug overlay)
+    #[clap(long)]
+    draw_hit_rects: bool,
+
+    /// Read documents in this encoding, unless they start with a byte order mark
+    #[clap(long)]
+    encoding: Option<String>,
+
+    /// Keep cookies in this file between runs
+    #[clap(long)]
+    cookies: Option<String>,
+
+    /// Keep visited links in this file instead of the data directory
+    #[clap(long)]
+    visited: Option<String>,
 
     file: Option<String>,
 }
@@ -87,9 +140,36 @@ async fn main() -> Result<(), String> {
     let video_subsys = sdl_context.video()?;
     let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string())?;
     let args = Args::parse();
+    if args.list_themes {
+        for name in theme_names() {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+    let mut config = Config::load(args.config.as_ref().map(PathBuf::from))?;
+    if let Some(theme) = &args.color_theme {
+        config.theme = theme.clone();
+    }
+    config.window_width = args.width.unwrap_or(config.window_width);
+    config.window_height = args.height.unwrap_or(config.window_height);
+    config.font = args.font.clone().or(config.font);
+    config.mono_font = args.mono_font.clone().or(config.mono_font);
+    config.font_size = args.font_size.unwrap_or(config.font_size);
+    config.scroll_speed = args.scroll_speed.unwrap_or(config.scroll_speed);
+    config.cache_dir = args
+        .cache_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .or(config.cache_dir);
+    config.draw_hit_rects |= args.draw_hit_rects;
+    let color_scheme = load_theme(&config.theme)?;
 
     let window = video_subsys
-        .window("SDL2_TTF Example", SCREEN_WIDTH, SCREEN_HEIGHT)
+        .window(
+            "SDL2_TTF Example",
+            config.window_width,
+            config.window_height,
+        )
         .position_centered()
         .resizable()
         .vulkan()
@@ -100,11 +180,16 @@ async fn main() -> Result<(), String> {
     canvas.window_mut().set_minimum_size(400, 400).unwrap();
     let texture_creator = canvas.texture_creator();
 
-    let mut input: Box<dyn BufRead> = match args.file {
-        None => Box::new(BufReader::new(io::stdin())),
-        Some(filename) => Box::new(BufReader::new(
-            fs::File::open(filename).expect("Couldn't open file"),
-        )),
+    // A document piped in through stdin can't be loaded again, so its source is kept around
+    let mut stdin_source = Vec::new();
+    let location = match args.file {
+        None => {
+            io::stdin()
+                .read_to_end(&mut stdin_source)
+                .map_err(|e| e.to_string())?;
+            Url::parse("about:stdin").unwrap()
+        }
+        Some(ref arg) => location_from_arg(arg)?,
     };
 
     /* let mut strstr = String::new();
@@ -120,35 +205,118 @@ async fn main() -> Result<(), String> {
      *     }
      * }); */
 
-    let dom = parse_document(RcDom::default(), Default::default())
-        .from_utf8()
-        .read_from(&mut input)
-        .unwrap();
+    let encoding = match args.encoding {
+        Some(ref label) => Some(
+            Encoding::for_label(label.as_bytes())
+                .ok_or_else(|| format!("Unknown encoding: {}", label))?,
+        ),
+        None => None,
+    };
+    let mut cookies = CookieJar::load(args.cookies.clone());
+    let mut dom = match args.file {
+        None => parse_html(&decode(&stdin_source, None, encoding)),
+        Some(_) => load_document(&location, None, &mut cookies, Initiator::User, encoding).await?,
+    };
+    let mut history = History::new(location.clone());
+    let mut visited = VisitedLinks::load(
+        args.visited
+            .as_ref()
+            .map(PathBuf::from)
+            .or_else(visited::default_path),
+    );
+    visited.add(&location);
     // print_dom(0, &dom.document);
 
     let sf = canvas.output_size().unwrap().0 / canvas.window().size().0;
+    // Try each system font in order, falling back to the bundled one
     macro_rules! load_font {
-        () => {
-            ttf_context
-                .load_font("/usr/share/fonts/TTF/Times.TTF", 50 * sf as u16)
-                .unwrap_or_else(|_| {
+        ($preferred:expr, $($path:expr),*) => {{
+            let preferred: &Option<String> = $preferred;
+            preferred
+                .iter()
+                .map(|path| path.as_str())
+                .chain([$($path),*].iter().copied())
+                .find_map(|path| ttf_context.load_font(path, 50 * sf as u16).ok())
+                .unwrap_or_else(|| {
                     ttf_context
                         .load_font("assets/trim.ttf", 50 * sf as u16)
                         .expect("Could neither load system font nor fallback!")
                 })
+        }};
+    }
+    macro_rules! load_regular_font {
+        () => {
+            load_font!(&config.font, "/usr/share/fonts/TTF/Times.TTF")
+        };
+    }
+    macro_rules! load_mono_font {
+        () => {
+            load_font!(
+                &config.mono_font,
+                "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+                "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+                "/usr/share/fonts/TTF/LiberationMono-Regular.ttf",
+                "/usr/share/fonts/truetype/liberation/LiberationMono-Regular.ttf",
+                "/usr/share/fonts/TTF/cour.ttf"
+            )
         };
     }
     let mut rc = RendererContext {
         canvas: Rc::new(RefCell::new(canvas)),
-        font: Rc::new(RefCell::new(load_font!())),
+        font: Rc::new(RefCell::new(load_regular_font!())),
+        mono_font: Rc::new(RefCell::new(load_mono_font!())),
         texture_creator: Rc::new(texture_creator),
         scaling_factor: sf,
+        zoom: 1.0,
         images: HashMap::new(),
+        cache_dir: config.cache_dir.clone(),
+        location,
+        cookies,
+        visited,
+        anchors: HashMap::new(),
         viewport: (0, 0),
         hit_map: Vec::new(),
-        color_scheme: args.color_theme.value(),
+        color_scheme,
+        forced_colors: args.forced_colors,
         indices: (12, 12),
+        style: Style {
+            font_size: config.font_size,
+            ..Style::default()
+        },
+        line: Vec::new(),
+        paragraph: Vec::new(),
+        paragraph_anchors: Vec::new(),
+        pending_space: false,
+        display_list: Vec::new(),
+        blocks: Vec::new(),
+        pending_margin: (0, 0),
+        block_count: 0,
+        embedding_count: 0,
+        selection: None,
+        find: None,
+        focusables: Vec::new(),
+        focus: None,
+        controls: Vec::new(),
+        control_count: 0,
+        forms: Vec::new(),
+        hovered_link: None,
+        document_size: (0, 0),
+        scroll_velocity: (0.0, 0.0),
+        scroll_remainder: (0.0, 0.0),
+        debug: DebugOverlays {
+            shown: config.draw_hit_rects,
+            overlay: if config.draw_hit_rects {
+                Overlay::HitRects
+            } else {
+                Overlay::All
+            },
+            ..DebugOverlays::default()
+        },
     };
+    // Whether the left button went down on a single click and is extending the selection
+    let mut selecting = false;
+    // Scrollbar whose thumb is being dragged and where along the thumb it was grabbed
+    let mut dragging: Option<(Axis, i32)> = None;
 
     rc.canvas
         .borrow_mut()
@@ -159,43 +327,536 @@ async fn main() -> Result<(), String> {
 
     rc.canvas.borrow_mut().present();
 
+    // Draw the existing layout again after only its highlights or overlays changed
+    macro_rules! repaint {
+        () => {
+            paint(&rc);
+            rc.canvas.borrow_mut().present();
+        };
+    }
+
+    render_document(&dom.document, &mut rc).await;
+    scroll_to_fragment(&mut rc, history.current().location.fragment());
+    repaint!();
+
+    // Last known pointer position in window coordinates
+    let mut mouse = (0, 0);
+    // Re-layout at another zoom level, keeping the content under the mouse in place.
+    // Lines reflow, so vertical positions are mapped through the document height.
+    macro_rules! zoom_to {
+        ($zoom:expr) => {
+            let zoom = $zoom;
+            if zoom != rc.zoom {
+                let sf = rc.scaling_factor as i32;
+                let (x, y) = (mouse.0 * sf, mouse.1 * sf);
+                let (document_x, document_y) = (x - rc.viewport.0, y - rc.viewport.1);
+                let previous_height = rc.document_size.1.max(1) as f32;
+                let ratio = zoom / rc.zoom;
+                rc.zoom = zoom;
+                rc.selection = None;
+                stop_scroll(&mut rc);
+                render_document(&dom.document, &mut rc).await;
+                let document_y = document_y as f32 * rc.document_size.1 as f32 / previous_height;
+                rc.viewport = (
+                    x - (document_x as f32 * ratio) as i32,
+                    y - document_y as i32,
+                );
+                clamp_viewport(&mut rc);
+                update_matches(&mut rc);
+                repaint!();
+            }
+        };
+    }
+    // Show a location, loading its document unless only the fragment differs from the current
+    // one or a form is posted to it. The viewport of a history entry is restored, otherwise the
+    // fragment is scrolled to. Evaluates to whether the location could be shown.
+    macro_rules! show {
+        ($location:expr, $viewport:expr, $post:expr, $initiator:expr) => {{
+            let location: Url = $location;
+            let post: Option<(String, Vec<u8>)> = $post;
+            let loaded = if post.is_none() && same_document(&location, &rc.location) {
+                // Links to the fragment are drawn as visited from now on
+                if rc.visited.add(&location) {
+                    rc.selection = None;
+                    render_document(&dom.document, &mut rc).await;
+                    update_matches(&mut rc);
+                }
+                true
+            } else {
+                let document = if location.as_str().starts_with("about:stdin") {
+                    Ok(parse_html(&decode(&stdin_source, None, encoding)))
+                } else {
+                    let initiator: Option<Url> = $initiator;
+                    let initiator = match initiator {
+                        Some(ref from) => Initiator::Navigation(from),
+                        None => Initiator::User,
+                    };
+                    load_document(
+                        &location,
+                        post.as_ref(),
+                        &mut rc.cookies,
+                        initiator,
+                        encoding,
+                    )
+                    .await
+                };
+                match document {
+                    Ok(document) => {
+                        dom = document;
+                        rc.location = location.clone();
+                        rc.focus = None;
+                        rc.controls.clear();
+                        rc.viewport = (0, 0);
+                        rc.selection = None;
+                        rc.visited.add(&location);
+                        stop_scroll(&mut rc);
+                        render_document(&dom.document, &mut rc).await;
+                        update_matches(&mut rc);
+                        true
+                    }
+                    Err(e) => {
+                        println!("Couldn't load {}: {}", location, e);
+                        false
+                    }
+                }
+            };
+            if loaded {
+                rc.location = location.clone();
+                match $viewport {
+                    Some(viewport) => {
+                        stop_scroll(&mut rc);
+                        rc.viewport = viewport;
+                        clamp_viewport(&mut rc);
+                    }
+                    None => scroll_to_fragment(&mut rc, location.fragment()),
+                }
+                repaint!();
+            }
+            loaded
+        }};
+    }
+    // Go back (negative) or forward (positive) through the history
+    macro_rules! go {
+        ($delta:expr) => {
+            if let Some(entry) = history.peek($delta).cloned() {
+                history.save_viewport(rc.viewport);
+                if show!(entry.location, entry.viewport, None, None) {
+                    history.go($delta);
+                }
+            }
+        };
+    }
+    // System cursors have to stay alive while they are shown
+    let cursors: HashMap<SystemCursor, Cursor> =
+        [SystemCursor::Arrow, SystemCursor::Hand, SystemCursor::IBeam]
+            .iter()
+            .map(|&kind| Ok((kind, Cursor::from_system(kind)?)))
+            .collect::<Result<_, String>>()?;
+    let mut cursor = SystemCursor::Arrow;
+    // Hit-test the pointer to pick the cursor and the link shown in the status strip
+    macro_rules! hover {
+        () => {
+            let sf = rc.scaling_factor as i32;
+            let (x, y) = (mouse.0 * sf, mouse.1 * sf);
+            let hit_rect = if scrollbar_at(&rc, x, y).is_some() || dragging.is_some() {
+                None
+            } else {
+                let (x, y) = (x - rc.viewport.0, y - rc.viewport.1);
+                rc.hit_map
+                    .iter()
+                    .rev()
+                    .find(|hit_rect| hit_rect.contains(x, y))
+            };
+            let kind = match hit_rect {
+                Some(hit_rect) if hit_rect.href.is_some() => SystemCursor::Hand,
+                Some(hit_rect) if hit_rect.text => SystemCursor::IBeam,
+                _ => SystemCursor::Arrow,
+            };
+            let link = hit_rect
+                .and_then(|hit_rect| hit_rect.href.as_ref())
+                .map(|href| {
+                    rc.location
+                        .join(href)
+                        .map(|location| location.to_string())
+                        .unwrap_or_else(|_| href.clone())
+                });
+            if kind != cursor {
+                cursor = kind;
+                cursors[&kind].set();
+            }
+            if link != rc.hovered_link {
+                rc.hovered_link = link;
+                repaint!();
+            }
+        };
+    }
+    // Navigate to a link target, adding it to the history
+    macro_rules! follow {
+        ($href:expr) => {
+            let href: String = $href;
+            match rc.location.join(&href) {
+                Ok(location) => {
+                    history.save_viewport(rc.viewport);
+                    if show!(location.clone(), None, None, Some(rc.location.clone())) {
+                        history.push(location);
+                    }
+                    hover!();
+                }
+                Err(e) => println!("Invalid link {}: {}", href, e),
+            }
+        };
+    }
+    // Send a form and show the response, adding it to the history
+    macro_rules! submit {
+        ($submission:expr) => {
+            let submission: Submission = $submission;
+            history.save_viewport(rc.viewport);
+            if show!(
+                submission.location.clone(),
+                None,
+                submission.post,
+                Some(rc.location.clone())
+            ) {
+                history.push(submission.location);
+            }
+            hover!();
+        };
+    }
+    // Follow a focused link or operate a focused control
+    macro_rules! activate {
+        ($focus:expr) => {
+            match rc.focusables[$focus].clone() {
+                Focusable::Link { href } => {
+                    follow!(href);
+                }
+                Focusable::Control { index } => match activate(&mut rc, index) {
+                    Some(submission) => {
+                        submit!(submission);
+                    }
+                    None => {
+                        repaint!();
+                    }
+                },
+            }
+        };
+    }
+    // Take text input while the find bar is open or a text field has focus
+    macro_rules! update_text_input {
+        () => {
+            if rc.find.is_some() || editing_text(&rc) {
+                video_subsys.text_input().start();
+            } else {
+                video_subsys.text_input().stop();
+            }
+        };
+    }
+    let mut last_frame = Instant::now();
     'mainloop: loop {
         for event in sdl_context.event_pump()?.poll_iter() {
+            if let Event::MouseMotion { x, y, .. } = event {
+                mouse = (x, y);
+                hover!();
+            }
             match event {
+                Event::KeyDown {
+                    keycode: Some(Keycode::F),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    if rc.find.is_none() {
+                        rc.find = Some(FindBar::default());
+                        video_subsys.text_input().start();
+                    }
+                    repaint!();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    keymod,
+                    ..
+                } => {
+                    // Shift+F12 shows the next layer of the overlay
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) && rc.debug.shown {
+                        rc.debug.overlay = rc.debug.overlay.next();
+                    } else {
+                        rc.debug.shown = !rc.debug.shown;
+                    }
+                    repaint!();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if rc.find.is_some() => {
+                    rc.find = None;
+                    update_text_input!();
+                    repaint!();
+                }
+                Event::TextInput { ref text, .. } if rc.find.is_some() => {
+                    rc.find.as_mut().unwrap().query.push_str(text);
+                    update_matches(&mut rc);
+                    scroll_to_match(&mut rc);
+                    repaint!();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } if rc.find.is_some() => {
+                    rc.find.as_mut().unwrap().query.pop();
+                    update_matches(&mut rc);
+                    scroll_to_match(&mut rc);
+                    repaint!();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return | Keycode::KpEnter),
+                    keymod,
+                    ..
+                } if rc.find.is_some() => {
+                    cycle_match(&mut rc, keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD));
+                    scroll_to_match(&mut rc);
+                    repaint!();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    keymod,
+                    ..
+                } => {
+                    cycle_focus(&mut rc, keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD));
+                    update_text_input!();
+                    stop_scroll(&mut rc);
+                    scroll_to_focus(&mut rc);
+                    repaint!();
+                }
+                Event::TextInput { ref text, .. } if editing_text(&rc) => {
+                    let index = focused_control(&rc).unwrap();
+                    rc.controls[index].value.push_str(text);
+                    repaint!();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } if editing_text(&rc) => {
+                    let index = focused_control(&rc).unwrap();
+                    rc.controls[index].value.pop();
+                    repaint!();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return | Keycode::KpEnter),
+                    ..
+                } if rc.focus.is_some() => {
+                    let focus = rc.focus.unwrap();
+                    match focused_control(&rc).map(|index| (index, rc.controls[index].kind)) {
+                        Some((index, ControlKind::TextArea)) => {
+                            rc.controls[index].value.push('\n');
+                            repaint!();
+                        }
+                        // Enter in a text field submits its form
+                        Some((index, ControlKind::Text | ControlKind::Password)) => {
+                            let submission = rc.controls[index]
+                                .form
+                                .and_then(|form| submit(&rc, form, None));
+                            if let Some(submission) = submission {
+                                submit!(submission);
+                            }
+                        }
+                        _ => {
+                            activate!(focus);
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } if focused_control(&rc).is_some_and(|index| !edits_text(&rc.controls[index])) => {
+                    activate!(rc.focus.unwrap());
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::Up | Keycode::Down)),
+                    ..
+                } if focused_control(&rc)
+                    .is_some_and(|index| rc.controls[index].kind == ControlKind::Select) =>
+                {
+                    let index = focused_control(&rc).unwrap();
+                    select_option(&mut rc, index, if keycode == Keycode::Up { -1 } else { 1 });
+                    repaint!();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if rc.focus.is_some() => {
+                    rc.focus = None;
+                    update_text_input!();
+                    repaint!();
+                }
                 Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 }
                 | Event::Quit { .. } => break 'mainloop,
-                Event::MouseWheel { x, y, .. } => {
-                    rc.viewport.0 += x * SCROLL_SPEED;
-                    rc.viewport.1 += y * SCROLL_SPEED;
-                    if rc.viewport.1 > 0 {
-                        rc.viewport.1 = 0;
-                    }
-                    rc.canvas.borrow_mut().clear();
-                    rc.hit_map.clear();
-                    render(0, &dom.document, "", &mut rc).await;
-                    rc.canvas.borrow_mut().present();
-                    rc.indices.1 = 0;
+                Event::KeyDown {
+                    keycode:
+                        Some(
+                            keycode @ (Keycode::Equals
+                            | Keycode::Plus
+                            | Keycode::KpPlus
+                            | Keycode::Minus
+                            | Keycode::KpMinus
+                            | Keycode::Num0
+                            | Keycode::Kp0),
+                        ),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    zoom_to!(match keycode {
+                        Keycode::Minus | Keycode::KpMinus => zoom_level(rc.zoom, -1),
+                        Keycode::Num0 | Keycode::Kp0 => 1.0,
+                        _ => zoom_level(rc.zoom, 1),
+                    });
+                }
+                Event::MouseWheel { y, direction, .. }
+                    if sdl_context
+                        .keyboard()
+                        .mod_state()
+                        .intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) =>
+                {
+                    let y = match direction {
+                        MouseWheelDirection::Flipped => -y,
+                        _ => y,
+                    };
+                    zoom_to!(zoom_level(rc.zoom, y.signum()));
+                }
+                Event::MouseWheel {
+                    precise_x,
+                    precise_y,
+                    direction,
+                    ..
+                } => {
+                    // Animated from the main loop below
+                    let flip = match direction {
+                        MouseWheelDirection::Flipped => -1.0,
+                        _ => 1.0,
+                    };
+                    let speed = (config.scroll_speed * rc.scaling_factor as i32) as f32 * flip;
+                    fling(&mut rc, -precise_x * speed, -precise_y * speed);
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::Left | Keycode::Right)),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) => {
+                    go!(if keycode == Keycode::Left { -1 } else { 1 });
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: mouse_btn @ (MouseButton::X1 | MouseButton::X2),
+                    ..
+                } => {
+                    go!(if mouse_btn == MouseButton::X1 { -1 } else { 1 });
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::C),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    let text = selected_text(&rc);
+                    if !text.is_empty() {
+                        video_subsys.clipboard().set_clipboard_text(&text)?;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } => {
+                    let (width, height) = rc.canvas.borrow().output_size().unwrap();
+                    let step = 40 * rc.scaling_factor as i32;
+                    let page = height as i32 - step;
+                    let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    let typing = rc.find.is_some() || editing_text(&rc);
+                    let (dx, dy) = match keycode {
+                        Keycode::Up => (0, -step),
+                        Keycode::Down => (0, step),
+                        Keycode::Left => (-step, 0),
+                        Keycode::Right => (step, 0),
+                        Keycode::PageUp => (0, -page),
+                        Keycode::PageDown => (0, page),
+                        Keycode::Space if typing => continue,
+                        Keycode::Space if shift => (0, -page),
+                        Keycode::Space => (0, page),
+                        Keycode::Home => (-(width as i32), -(rc.document_size.1 as i32)),
+                        Keycode::End => (0, rc.document_size.1 as i32),
+                        _ => continue,
+                    };
+                    stop_scroll(&mut rc);
+                    if scroll_by(&mut rc, dx, dy) {
+                        repaint!();
+                    }
                 }
                 Event::MouseButtonDown {
                     mouse_btn: MouseButton::Left,
+                    clicks,
                     x,
                     y,
                     ..
                 } => {
-                    for hit_rect in &rc.hit_map {
-                        // println!("{:?}", hit_rect);
-                        if hit_rect.0 <= x * rc.scaling_factor as i32
-                            && hit_rect.0 + hit_rect.2 as i32 >= x * rc.scaling_factor as i32
-                            && hit_rect.1 <= y * rc.scaling_factor as i32
-                            && hit_rect.1 + hit_rect.3 as i32 >= y * rc.scaling_factor as i32
-                        {
-                            hit_rect.4();
+                    let sf = rc.scaling_factor as i32;
+                    match scrollbar_at(&rc, x * sf, y * sf) {
+                        Some((axis, true)) => {
+                            dragging = Some((axis, thumb_offset(&rc, axis, x * sf, y * sf)));
+                            continue;
                         }
+                        Some((axis, false)) => {
+                            let (dx, dy) = page_towards(&rc, axis, x * sf, y * sf);
+                            scroll_by(&mut rc, dx, dy);
+                            repaint!();
+                            continue;
+                        }
+                        None => {}
+                    }
+                    // Hit rects are in document coordinates
+                    let (x, y) = (x * sf - rc.viewport.0, y * sf - rc.viewport.1);
+                    // Clicking a link or control focuses and activates it, anything else blurs
+                    rc.focus = rc
+                        .hit_map
+                        .iter()
+                        .rev()
+                        .find(|hit_rect| hit_rect.contains(x, y) && hit_rect.focus.is_some())
+                        .and_then(|hit_rect| hit_rect.focus);
+                    update_text_input!();
+                    if let Some(focus) = rc.focus {
+                        activate!(focus);
+                        continue;
+                    }
+                    rc.selection = position_at(&rc, x, y).map(|position| match clicks {
+                        1 => (position, position),
+                        2 => word_at(&rc, position),
+                        _ => paragraph_at(&rc, position),
+                    });
+                    selecting = clicks == 1;
+                    repaint!();
+                }
+                Event::MouseMotion { x, y, .. } if dragging.is_some() => {
+                    let (axis, grab) = dragging.unwrap();
+                    let sf = rc.scaling_factor as i32;
+                    stop_scroll(&mut rc);
+                    drag_thumb(&mut rc, axis, x * sf, y * sf, grab);
+                    repaint!();
+                }
+                Event::MouseMotion {
+                    mousestate, x, y, ..
+                } if selecting && mousestate.left() => {
+                    let sf = rc.scaling_factor as i32;
+                    let (x, y) = (x * sf - rc.viewport.0, y * sf - rc.viewport.1);
+                    if let (Some((anchor, _)), Some(focus)) = (rc.selection, position_at(&rc, x, y))
+                    {
+                        rc.selection = Some((anchor, focus));
+                        repaint!();
                     }
                 }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => {
+                    selecting = false;
+                    dragging = None;
+                }
                 Event::Window { win_event, .. } => match win_event {
                     WindowEvent::Resized(w, h) => {
                         rc.canvas
@@ -203,36 +864,32 @@ async fn main() -> Result<(), String> {
                             .window_mut()
                             .set_size(w as u32, h as u32)
                             .unwrap();
-                        rc.canvas
-                            .borrow_mut()
-                            .set_draw_color(rc.color_scheme.background);
-                        rc.canvas.borrow_mut().clear();
-                        rc.hit_map.clear();
-                        render(0, &dom.document, "", &mut rc).await;
-                        if DRAW_HITRECTS {
-                            for hit_rect in &rc.hit_map {
-                                rc.canvas.borrow_mut().set_draw_color(Color::RED);
-                                rc.canvas.borrow_mut().draw_rect(rect!(
-                                    hit_rect.0, hit_rect.1, hit_rect.2, hit_rect.3
-                                ))?;
-                            }
-                        }
-                        rc.canvas.borrow_mut().present();
-                        rc.indices.1 = 0;
+                        rc.selection = None;
+                        render_document(&dom.document, &mut rc).await;
+                        update_matches(&mut rc);
+                        repaint!();
                     }
                     _ => {
                         let c = rc.canvas.borrow_mut();
                         if c.output_size().unwrap().0 / c.window().size().0 != rc.scaling_factor {
                             rc.scaling_factor = c.output_size().unwrap().0 / c.window().size().0;
-                            rc.font = Rc::new(RefCell::new(load_font!()));
+                            rc.font = Rc::new(RefCell::new(load_regular_font!()));
+                            rc.mono_font = Rc::new(RefCell::new(load_mono_font!()));
                         }
                     }
                 },
                 _ => {}
             }
         }
+        let now = Instant::now();
+        if step_scroll(&mut rc, (now - last_frame).as_secs_f32()) {
+            repaint!();
+            hover!();
+        }
+        last_frame = now;
         sdl_context.timer()?.delay(1000 / 165);
     }
+    rc.cookies.save();
 
     Ok(())
 }
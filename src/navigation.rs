@@ -0,0 +1,195 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use html5ever::parse_document;
+use html5ever::tendril::TendrilSink;
+
+use hyper::header::{CONTENT_TYPE, COOKIE, SET_COOKIE};
+use hyper::{Body, Request, Uri};
+
+use encoding_rs::Encoding;
+
+use percent_encoding::percent_decode_str;
+
+use rcdom::RcDom;
+
+use url::{Position, Url};
+
+use crate::cookies::{CookieJar, Initiator};
+use crate::encoding::decode;
+use crate::renderer::{clamp_viewport, stop_scroll, RendererContext};
+
+// A visited location and the viewport it was left at, restored when going back to it
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub location: Url,
+    pub viewport: Option<(i32, i32)>,
+}
+
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    index: usize,
+}
+impl History {
+    pub fn new(location: Url) -> History {
+        History {
+            entries: vec![HistoryEntry {
+                location,
+                viewport: None,
+            }],
+            index: 0,
+        }
+    }
+
+    pub fn current(&self) -> &HistoryEntry {
+        &self.entries[self.index]
+    }
+
+    pub fn save_viewport(&mut self, viewport: (i32, i32)) {
+        self.entries[self.index].viewport = Some(viewport);
+    }
+
+    // Add a location after the current entry, dropping the entries that could be gone forward to.
+    // Form bodies aren't kept, going back to a submitted form's response fetches it again
+    // without sending the form.
+    pub fn push(&mut self, location: Url) {
+        self.entries.truncate(self.index + 1);
+        self.entries.push(HistoryEntry {
+            location,
+            viewport: None,
+        });
+        self.index += 1;
+    }
+
+    // The entry `delta` steps back (negative) or forward (positive), if there is one
+    pub fn peek(&self, delta: i32) -> Option<&HistoryEntry> {
+        let index = self.index as i32 + delta;
+        if index < 0 {
+            return None;
+        }
+        self.entries.get(index as usize)
+    }
+
+    pub fn go(&mut self, delta: i32) {
+        if self.peek(delta).is_some() {
+            self.index = (self.index as i32 + delta) as usize;
+        }
+    }
+}
+
+// Location of a command line argument, either a URL or a local file with an optional fragment
+pub fn location_from_arg(arg: &str) -> Result<Url, String> {
+    if arg.contains("://") {
+        return Url::parse(arg).map_err(|e| format!("Invalid URL {}: {}", arg, e));
+    }
+    let (path, fragment) = match arg.rsplit_once('#') {
+        Some((path, fragment)) if !Path::new(arg).exists() => (path, Some(fragment)),
+        _ => (arg, None),
+    };
+    let path = fs::canonicalize(path).map_err(|e| format!("Couldn't open {}: {}", path, e))?;
+    let mut location =
+        Url::from_file_path(&path).map_err(|_| format!("Couldn't open {}", path.display()))?;
+    location.set_fragment(fragment);
+    Ok(location)
+}
+
+// Whether two locations only differ in their fragment
+pub fn same_document(a: &Url, b: &Url) -> bool {
+    a[..Position::AfterQuery] == b[..Position::AfterQuery]
+}
+
+// Read a local file or request a remote one, posting the content type and body if given.
+// Cookies are sent with requests and stored from responses.
+// Returns the body and, for HTTP, the Content-Type header.
+pub async fn fetch(
+    location: &Url,
+    post: Option<&(String, Vec<u8>)>,
+    cookies: &mut CookieJar,
+    initiator: Initiator<'_>,
+) -> Result<(Vec<u8>, Option<String>), String> {
+    match location.scheme() {
+        "http" | "https" => {
+            println!("Requesting {}...", location);
+            let uri =
+                Uri::from_str(&location[..Position::AfterQuery]).map_err(|e| e.to_string())?;
+            let mut request = match post {
+                Some((content_type, body)) => Request::post(uri)
+                    .header(CONTENT_TYPE, content_type)
+                    .body(Body::from(body.clone())),
+                None => Request::get(uri).body(Body::empty()),
+            }
+            .map_err(|e| e.to_string())?;
+            if let Some(cookie) = cookies.cookie_header(location, initiator, post.is_none()) {
+                request.headers_mut().insert(
+                    COOKIE,
+                    cookie.parse().map_err(|_| "Invalid cookie".to_string())?,
+                );
+            }
+            let res = hyper::Client::new()
+                .request(request)
+                .await
+                .map_err(|e| e.to_string())?;
+            for header in res.headers().get_all(SET_COOKIE) {
+                if let Ok(header) = header.to_str() {
+                    cookies.set_cookie(location, header);
+                }
+            }
+            let content_type = res
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|header| header.to_str().ok())
+                .map(String::from);
+            hyper::body::to_bytes(res)
+                .await
+                .map(|bytes| (bytes.to_vec(), content_type))
+                .map_err(|e| e.to_string())
+        }
+        "file" => {
+            let path = location
+                .to_file_path()
+                .map_err(|_| format!("Not a local file: {}", location))?;
+            fs::read(path)
+                .map(|bytes| (bytes, None))
+                .map_err(|e| e.to_string())
+        }
+        scheme => Err(format!("Unsupported scheme: {}", scheme)),
+    }
+}
+
+pub fn parse_html(text: &str) -> RcDom {
+    parse_document(RcDom::default(), Default::default()).one(text)
+}
+
+// Fetch and parse a document, decoding it as `encoding` if the user chose one
+pub async fn load_document(
+    location: &Url,
+    post: Option<&(String, Vec<u8>)>,
+    cookies: &mut CookieJar,
+    initiator: Initiator<'_>,
+    encoding: Option<&'static Encoding>,
+) -> Result<RcDom, String> {
+    let (bytes, content_type) = fetch(location, post, cookies, initiator).await?;
+    Ok(parse_html(&decode(
+        &bytes,
+        content_type.as_deref(),
+        encoding,
+    )))
+}
+
+// Scroll the element the fragment names to the top of the window.
+// An empty fragment or `top` without a matching element means the top of the document.
+pub fn scroll_to_fragment(context: &mut RendererContext, fragment: Option<&str>) {
+    let name = match fragment {
+        Some(fragment) => percent_decode_str(fragment).decode_utf8_lossy(),
+        None => return,
+    };
+    let y = match context.anchors.get(&*name) {
+        Some(&y) => y,
+        None if name.is_empty() || name.eq_ignore_ascii_case("top") => 0,
+        None => return,
+    };
+    stop_scroll(context);
+    context.viewport.1 = -(y as i32);
+    clamp_viewport(context);
+}
@@ -1,26 +1,25 @@
-use std::borrow::Borrow;
 use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 
+use std::fs;
 use std::iter::repeat;
 use std::ops::RangeBounds;
-use std::path::Path;
+use std::path::PathBuf;
 use std::rc::Rc;
-use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 use async_recursion::async_recursion;
 
 use html5ever::tendril::TendrilSink;
 
-use hyper::Uri;
-
-use sdl2::image::{LoadSurface, LoadTexture};
+use sdl2::image::LoadTexture;
 
 use sdl2::libc::printf;
-use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::pixels::Color;
 use sdl2::rect::Rect;
-use sdl2::render::{Texture, TextureCreator, WindowCanvas};
-use sdl2::surface::Surface;
+use sdl2::render::{TextureCreator, WindowCanvas};
 use sdl2::ttf::FontStyle;
 use sdl2::video::WindowContext;
 
@@ -28,7 +27,27 @@ use std::string::String;
 
 use rcdom::{Handle, NodeData};
 
+use unicode_segmentation::UnicodeSegmentation;
+use url::Url;
+
+use crate::alignment::align_line;
+use crate::bidi::{reorder_paragraph, visual_order};
 use crate::colorscheme::ColorScheme;
+use crate::cookies::{CookieJar, Initiator};
+use crate::debug::{paint_debug_overlays, DebugOverlays, LayoutBox};
+use crate::find::{match_ranges, paint_find_bar, FindBar};
+use crate::focus::{paint_focus_ring, Focusable};
+use crate::forms::{add_control, control_for, edits_text, form_for, paint_control, Control, Form};
+use crate::navigation::fetch;
+use crate::scrollbar::paint_scrollbars;
+use crate::selection::{prefix_width, selected_range, TextPosition};
+use crate::shaping::{font_runs, render_shaped, shaped_width};
+use crate::status::paint_status;
+use crate::style::{
+    expand_tabs, media_matches, BoxStyle, Direction, Display, LineHeight, Style, TextAlign,
+    UnicodeBidi,
+};
+use crate::visited::VisitedLinks;
 // handle the annoying Rect i32
 macro_rules! rect(
     ($x:expr, $y:expr, $w:expr, $h:expr) => (
@@ -36,27 +55,175 @@ macro_rules! rect(
     )
 );
 
+// How quickly kinetic scrolling slows down, as a fraction of the velocity per second
+static SCROLL_FRICTION: f32 = 10.0;
+// How long images in the cache directory are used instead of being fetched again
+static IMAGE_CACHE_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+// A run of text waiting on the current line until the line is full
+#[derive(Clone)]
+pub struct LineItem {
+    pub text: String,
+    pub x: u32,
+    pub width: u32,
+    pub style: Style,
+    // Index and height of a form control laid out in place of text
+    pub control: Option<(usize, u32)>,
+}
+
+// Something to draw once layout is done, positioned in document coordinates
+#[derive(Clone)]
+pub enum DisplayItem {
+    Rect {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        color: Color,
+    },
+    Text {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        text: String,
+        style: Style,
+        // Id of the innermost block the text was laid out in
+        block: usize,
+    },
+    Image {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        src: String,
+    },
+    Control {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        index: usize,
+    },
+}
+
+// Area of laid out text or an image in document coordinates, with the link it belongs to
+#[derive(Clone, Debug)]
+pub struct HitRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub href: Option<String>,
+    // Whether the area is text or a text field, shown with a text cursor
+    pub text: bool,
+    pub focus: Option<usize>,
+}
+impl HitRect {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        self.x <= x
+            && self.x + self.width as i32 >= x
+            && self.y <= y
+            && self.y + self.height as i32 >= y
+    }
+}
+
+// A block element whose contents are still being laid out
+#[derive(Clone)]
+pub struct BlockBox {
+    pub id: usize,
+    // Top of the border box, known once the collapsed margin above it has been placed
+    pub top: Option<u32>,
+    pub left: u32,
+    pub right: u32,
+    pub box_style: BoxStyle,
+    // Base direction of the lines, None when it comes from their first strong character
+    pub direction: Option<Direction>,
+    pub text_align: TextAlign,
+    // Position in the display list where the background and borders are inserted
+    pub display_index: usize,
+}
+
 #[derive(Clone)]
 pub struct RendererContext<'a> {
     pub canvas: Rc<RefCell<WindowCanvas>>,
     pub font: Rc<RefCell<sdl2::ttf::Font<'a, 'a>>>,
+    pub mono_font: Rc<RefCell<sdl2::ttf::Font<'a, 'a>>>,
     pub texture_creator: Rc<TextureCreator<WindowContext>>,
     pub scaling_factor: u32,
+    // User zoom on top of the scaling factor, applied to all document lengths
+    pub zoom: f32,
     pub images: HashMap<String, Vec<u8>>,
+    // Directory images fetched over HTTP are kept in between runs
+    pub cache_dir: Option<PathBuf>,
+    // Location of the document, relative links and image sources are resolved against it
+    pub location: Url,
+    pub cookies: CookieJar,
+    pub visited: VisitedLinks,
+    // Document position of every element id and named anchor, for fragment navigation
+    pub anchors: HashMap<String, u32>,
     pub viewport: (i32, i32),
-    pub hit_map: Vec<(i32, i32, u32, u32, fn())>,
+    pub hit_map: Vec<HitRect>,
     pub color_scheme: ColorScheme,
+    // Draw pages in the theme's colours, ignoring the background and border colours they set
+    pub forced_colors: bool,
     pub indices: (u32, u32),
+    pub style: Style,
+    pub line: Vec<LineItem>,
+    // Full lines of the current paragraph, laid out together once it ends so that the bidi
+    // algorithm sees all of it, and anchors in them with the index of their line
+    pub paragraph: Vec<Vec<LineItem>>,
+    pub paragraph_anchors: Vec<(String, usize)>,
+    pub pending_space: bool,
+    pub display_list: Vec<DisplayItem>,
+    pub blocks: Vec<BlockBox>,
+    // Largest positive and most negative margin not yet placed, collapsed into one
+    pub pending_margin: (i32, i32),
+    pub block_count: usize,
+    pub embedding_count: usize,
+    // Anchor and focus of the text selection
+    pub selection: Option<(TextPosition, TextPosition)>,
+    pub find: Option<FindBar>,
+    // Links and controls in document order, and the one with keyboard focus
+    pub focusables: Vec<Focusable>,
+    pub focus: Option<usize>,
+    // Form controls in document order, which keep their state when the document is laid out again
+    pub controls: Vec<Control>,
+    pub control_count: usize,
+    pub forms: Vec<Form>,
+    // Resolved target of the link under the pointer, shown in the status strip
+    pub hovered_link: Option<String>,
+    // Extent of the laid out content, the viewport is kept inside it
+    pub document_size: (u32, u32),
+    // Kinetic scrolling speed in output pixels per second
+    pub scroll_velocity: (f32, f32),
+    // Sub-pixel part of the scroll distance not yet applied to the viewport
+    pub scroll_remainder: (f32, f32),
+    pub debug: DebugOverlays,
 }
 #[async_recursion(?Send)]
-pub async fn render<'a>(
-    indent: usize,
-    handle: &Handle,
-    tag_name: &str,
-    context: &'a mut RendererContext,
-) {
+pub async fn render<'a>(handle: &Handle, tag_name: &str, context: &'a mut RendererContext) {
     let node = handle;
     let mut next_tag_name = "";
+    let mut children = true;
+    let mut element_style = context.style.for_element(node);
+    let mut box_style = BoxStyle::for_element(node, element_style.font_size);
+    if context.forced_colors {
+        box_style.background = None;
+        box_style.border_color = [None; 4];
+    }
+    if box_style.display == Display::None {
+        return;
+    }
+    let block = box_style.display == Display::Block;
+    if box_style.unicode_bidi != UnicodeBidi::Normal {
+        context.embedding_count += 1;
+        element_style.embeddings.push((
+            context.embedding_count,
+            box_style.unicode_bidi,
+            element_style.direction,
+        ));
+    }
     let invisible_tags = [
         "style", "script", "head", "title", "meta", "link", "img", "br",
     ];
@@ -71,136 +238,83 @@ pub async fn render<'a>(
                     .set_title(&contents.borrow())
                     .unwrap();
             }
-            if tag_name == "br" {
-                context.indices.1 += 12;
-            }
 
-            if &contents.borrow().trim().len() != &0 && !invisible_tags.contains(&tag_name) {
-                let mut overflow = String::new();
-                loop {
-                    let mut text_color = context.color_scheme.text;
-                    let mut text = contents.borrow().to_string().replace("\n", "");
-                    if overflow.len() > 0 {
-                        text = overflow;
-                        overflow = String::new();
-                    }
-                    let (mut width, mut height) = context.font.borrow_mut().size_of(&text).unwrap();
-                    let mut font_size = 16 * context.scaling_factor;
-                    if tag_name == "a" {
-                        context.font.borrow_mut().set_style(FontStyle::UNDERLINE);
-                        text_color = context.color_scheme.link;
-                    }
-                    if tag_name.starts_with("h") {
-                        let font_sizes = [32, 24, 19, 16, 13, 11];
-                        font_size = font_sizes[tag_name
-                            .chars()
-                            .nth(1)
-                            .unwrap_or('1')
-                            .to_string()
-                            .parse::<usize>()
-                            .unwrap()
-                            - 1]
-                            * context.scaling_factor;
-
-                        context.font.borrow_mut().set_style(FontStyle::BOLD);
+            if !invisible_tags.contains(&tag_name) {
+                let font = font_for(context, &context.style);
+                let font_size = scaled(context, context.style.font_size);
+                font.borrow_mut().set_style(context.style.font_style);
+                let white_space = context.style.white_space;
+                let text = expand_tabs(&contents.borrow());
+                for (i, segment) in text.split('\n').enumerate() {
+                    if i > 0 {
+                        if white_space.preserves_newlines() {
+                            line_break(context, line_height(context, &context.style));
+                        } else {
+                            context.pending_space = true;
+                        }
                     }
-
-                    let ratio = font_size as f32 / height as f32;
-                    width = (width as f32 * ratio).ceil() as u32;
-                    height = font_size;
-                    let mut c1 = 0;
-                    let c2 =
-                        context.canvas.borrow_mut().window().size().0 * context.scaling_factor * 2;
-                    let mut fitting_text = String::new();
-                    for (i, word) in text.split_whitespace().enumerate() {
-                        fitting_text.push_str(word);
-                        fitting_text.push(' ');
-                        // println!("{} < {}", c1, c2);
-                        // println!("<{}>", fitting_text);
-                        c1 += font_size * word.len() as u32;
-                        if c1 > c2 {
-                            overflow = text
-                                .split_whitespace()
-                                .skip(i)
-                                .collect::<Vec<_>>()
-                                .join(" ");
-                            fitting_text.truncate(
-                                fitting_text.len()
-                                    - fitting_text.split_whitespace().last().unwrap().len()
-                                    - 1,
-                            );
-                            break;
+                    // Preserved spaces stay attached to the preceding word, collapsible ones
+                    // are remembered and only emitted between two words on the same line
+                    let words: Vec<&str> = if white_space.preserves_spaces() {
+                        segment.split_inclusive(' ').collect()
+                    } else {
+                        if segment.starts_with(char::is_whitespace) {
+                            context.pending_space = true;
+                        }
+                        segment.split_whitespace().collect()
+                    };
+                    let mut item = None;
+                    for (j, word) in words.iter().enumerate() {
+                        if j > 0 && !white_space.preserves_spaces() {
+                            context.pending_space = true;
+                        }
+                        let style = &context.style;
+                        let space = if context.pending_space && !context.line.is_empty() {
+                            spaced_text_width(context, style, &font.borrow(), " ", font_size)
+                        } else {
+                            0
+                        };
+                        context.pending_space = false;
+                        let width =
+                            spaced_text_width(context, style, &font.borrow(), word, font_size);
+                        if white_space.wraps()
+                            && !context.line.is_empty()
+                            && context.indices.0 + space + width > line_right(context)
+                        {
+                            wrap_line(context);
+                            item = None;
+                        }
+                        let space = if context.line.is_empty() { 0 } else { space };
+                        match item {
+                            Some(index) => {
+                                let item: &mut LineItem = &mut context.line[index];
+                                if space > 0 {
+                                    item.text.push(' ');
+                                }
+                                item.text.push_str(word);
+                                item.width += space + width;
+                            }
+                            None => {
+                                item = Some(context.line.len());
+                                context.line.push(LineItem {
+                                    text: word.to_string(),
+                                    x: context.indices.0 + space,
+                                    width,
+                                    style: context.style.clone(),
+                                    control: None,
+                                });
+                            }
                         }
+                        context.indices.0 += space + width;
                     }
-                    text = fitting_text;
-                    width = context.font.borrow_mut().size_of(&text).unwrap().0;
-                    width = (width as f32 * ratio).ceil() as u32;
-                    // if text.contains("\n") {
-                    //     overflow = text.split("\n").last().unwrap().to_string();
-                    //     text.truncate(text.len() - overflow.len());
-                    // }
-                    // println!("==== START ====");
-                    // while c1 > c2 {
-                    //     // overflow.insert(0, text.pop().unwrap());
-                    //     // overflow.insert_str(
-                    //     //     0,
-                    //     //     &(text.split_whitespace().last().unwrap().to_owned() + &" ".to_owned()),
-                    //     // );
-                    //     overflow +=
-                    //         &(text.split_whitespace().last().unwrap().to_owned() + &" ".to_owned());
-                    //     println!("<{:?}>", overflow);
-                    //     if text.len() as i32 - overflow.len() as i32 > 1 {
-                    //         text.truncate(text.len() - overflow.len());
-                    //     }
-                    //     width = context.font.borrow_mut().size_of(&text).unwrap().0;
-                    //     width = (width as f32 * ratio).ceil() as u32;
-                    //     c1 = text.len() as u32 * font_size;
-                    // }
-                    // println!("==== END ====");
-
-                    let surface = context
-                        .font
-                        .borrow_mut()
-                        .render(text.as_str())
-                        .blended(text_color)
-                        .map_err(|e| e.to_string())
-                        .unwrap();
-                    let texture = context
-                        .texture_creator
-                        .create_texture_from_surface(&surface)
-                        .map_err(|e| e.to_string())
-                        .unwrap();
-
-                    context.hit_map.push((
-                        (context.indices.0 as i32 + context.viewport.0),
-                        context.indices.1 as i32 + context.viewport.1,
-                        width,
-                        height,
-                        || println!("Hello, I'm text"),
-                    ));
-                    context.indices.0 = indent as u32 * 12;
-
-                    context
-                        .canvas
-                        .borrow_mut()
-                        .copy(
-                            &texture,
-                            None,
-                            rect!(
-                                context.indices.0 as i32 + context.viewport.0,
-                                context.indices.1 as i32 + context.viewport.1,
-                                width,
-                                height
-                            ),
-                        )
-                        .unwrap();
-                    context.font.borrow_mut().set_style(FontStyle::NORMAL);
-                    context.indices.1 += height as u32;
-                    // println!("#text");
-                    if overflow.len() == 0 {
-                        break;
+                    if !white_space.preserves_spaces()
+                        && !words.is_empty()
+                        && segment.ends_with(char::is_whitespace)
+                    {
+                        context.pending_space = true;
                     }
                 }
+                font.borrow_mut().set_style(FontStyle::NORMAL);
             }
         }
         NodeData::Element {
@@ -211,79 +325,997 @@ pub async fn render<'a>(
             // println!("{}", &name.local);
 
             next_tag_name = &name.local;
+            if block {
+                open_block(context, box_style, &element_style);
+            }
+            // The first element with an id wins, like `getElementById`
+            let anchor = attrs
+                .borrow()
+                .iter()
+                .find(|a| &a.name.local == "id" || (&name.local == "a" && &a.name.local == "name"))
+                .map(|a| a.value.to_string());
+            if let Some(anchor) = anchor {
+                if context.paragraph.is_empty() {
+                    let (positive, negative) = context.pending_margin;
+                    let y = (context.indices.1 as i32 + positive + negative).max(0) as u32;
+                    context.anchors.entry(anchor).or_insert(y);
+                } else {
+                    // Placed with its line once the paragraph is laid out
+                    let line = context.paragraph.len();
+                    context.paragraph_anchors.push((anchor, line));
+                }
+            }
+            if let ("a", Some(href)) = (&*name.local, &element_style.href) {
+                element_style.visited = context
+                    .location
+                    .join(href)
+                    .is_ok_and(|location| context.visited.contains(&location));
+                context
+                    .focusables
+                    .push(Focusable::Link { href: href.clone() });
+                element_style.focus = Some(context.focusables.len() - 1);
+            }
+            if &name.local == "form" {
+                context.forms.push(form_for(node));
+                element_style.form = Some(context.forms.len() - 1);
+            }
+            if let Some(control) = control_for(node, context.style.form) {
+                add_control(context, control);
+                // The contents of these are the control's value or label
+                children = !matches!(&*name.local, "textarea" | "select" | "button");
+            }
+            if &name.local == "br" {
+                line_break(context, line_height(context, &element_style));
+            }
+
+            let src = match &*name.local {
+                "img" => picture_source(node, context.color_scheme.is_dark()).or_else(|| {
+                    attrs
+                        .borrow()
+                        .iter()
+                        .find(|a| &a.name.local == "src")
+                        .map(|a| a.value.to_string())
+                }),
+                _ => None,
+            };
+            if let Some(src) = src {
+                finish_line(context);
+                flush_margin(context);
+                if let Some((src, width, height)) = load_image(context, &src).await {
+                    let (x, y) = (context.indices.0 as i32, context.indices.1 as i32);
+                    context.hit_map.push(HitRect {
+                        x,
+                        y,
+                        width,
+                        height,
+                        href: context.style.href.clone(),
+                        text: false,
+                        focus: context.style.focus,
+                    });
+                    context.display_list.push(DisplayItem::Image {
+                        x,
+                        y,
+                        width,
+                        height,
+                        src,
+                    });
+                    context.indices.1 += height;
+                }
+            }
+        }
+        NodeData::ProcessingInstruction { .. } => unreachable!(),
+        _ => {}
+    }
+    let parent_style = std::mem::replace(&mut context.style, element_style);
+    if children {
+        for child in node.children.borrow().iter() {
+            render(child, next_tag_name, context).await;
+        }
+    }
+    if block {
+        close_block(context);
+    }
+    context.style = parent_style;
+}
 
-            if &name.local == "img" {
-                let img_path = attrs
-                    .borrow()
+// Image of the first `<source>` of a `<picture>` whose media query matches, for an `<img>` in it
+fn picture_source(img: &Handle, dark: bool) -> Option<String> {
+    let parent = img.parent.take()?.upgrade();
+    img.parent.set(parent.as_ref().map(Rc::downgrade));
+    let parent = parent?;
+    match parent.data {
+        NodeData::Element { ref name, .. } if &name.local == "picture" => {}
+        _ => return None,
+    }
+    let children = parent.children.borrow();
+    children.iter().find_map(|child| match child.data {
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } if &name.local == "source" => {
+            let attrs = attrs.borrow();
+            let attr = |attr_name: &str| {
+                attrs
                     .iter()
-                    .find(|a| &a.name.local == "src")
-                    .unwrap()
-                    .value
-                    .to_string();
-                let mut texture: Texture = context
-                    .texture_creator
-                    .create_texture_static(PixelFormatEnum::RGBA8888, 1, 1)
-                    .unwrap();
-                let (mut width, mut height) = (0, 0);
-                if img_path.starts_with("http://") || img_path.starts_with("https://") {
-                    if !context.images.contains_key(&img_path) {
-                        println!("Requesting {}...", img_path);
-                        let client = hyper::Client::new();
-                        let res = client
-                            .get(Uri::from_str(img_path.as_str()).unwrap())
-                            .await
-                            .unwrap();
-                        let bytes = hyper::body::to_bytes(res).await.unwrap().to_vec();
-                        context.images.insert(img_path.clone(), bytes);
+                    .find(|a| &a.name.local == attr_name)
+                    .map(|a| a.value.to_string())
+            };
+            if !media_matches(&attr("media").unwrap_or_default(), dark) {
+                return None;
+            }
+            // The first candidate of the srcset, without its descriptor
+            attr("srcset")?
+                .split(',')
+                .next()?
+                .split_whitespace()
+                .next()
+                .map(|url| url.to_string())
+        }
+        _ => None,
+    })
+}
+
+// File in the cache directory an image fetched over HTTP is kept in
+fn cached_image_path(context: &RendererContext, location: &Url) -> Option<PathBuf> {
+    if !matches!(location.scheme(), "http" | "https") {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    location.as_str().hash(&mut hasher);
+    let name = format!("{:016x}", hasher.finish());
+    Some(context.cache_dir.as_ref()?.join("images").join(name))
+}
+
+// An image from the cache directory, unless it is missing or too old to be used
+fn read_cached_image(context: &RendererContext, location: &Url) -> Option<Vec<u8>> {
+    let path = cached_image_path(context, location)?;
+    let age = fs::metadata(&path).ok()?.modified().ok()?.elapsed().ok()?;
+    if age > IMAGE_CACHE_LIFETIME {
+        return None;
+    }
+    fs::read(path).ok()
+}
+
+fn cache_image(context: &RendererContext, location: &Url, bytes: &[u8]) {
+    if let Some(path) = cached_image_path(context, location) {
+        let result =
+            fs::create_dir_all(path.parent().unwrap()).and_then(|_| fs::write(&path, bytes));
+        if let Err(e) = result {
+            println!("Couldn't cache image in {}: {}", path.display(), e);
+        }
+    }
+}
+
+// Fetch or read an image into the cache and return its resolved source and scaled size.
+// Documents without a base location (stdin) load images relative to the working directory.
+async fn load_image(context: &mut RendererContext<'_>, src: &str) -> Option<(String, u32, u32)> {
+    let location = context.location.join(src).ok();
+    let src = location
+        .as_ref()
+        .map(|location| location.to_string())
+        .unwrap_or_else(|| src.to_string());
+    if !context.images.contains_key(&src) {
+        let bytes = match location {
+            Some(location) => match read_cached_image(context, &location) {
+                Some(bytes) => Some(bytes),
+                None => {
+                    let bytes = fetch(
+                        &location,
+                        None,
+                        &mut context.cookies,
+                        Initiator::Subresource(&context.location),
+                    )
+                    .await
+                    .ok()
+                    .map(|(bytes, _)| bytes);
+                    if let Some(bytes) = &bytes {
+                        cache_image(context, &location, bytes);
                     }
+                    bytes
+                }
+            },
+            None => fs::read(&src).ok(),
+        };
+        match bytes {
+            Some(bytes) => context.images.insert(src.clone(), bytes),
+            None => {
+                println!("Couldn't load image: {}", src);
+                return None;
+            }
+        };
+    }
+    let texture = context
+        .texture_creator
+        .load_texture_bytes(context.images.get(&src).unwrap())
+        .ok()?;
+    let query = texture.query();
+    Some((
+        src,
+        scaled(context, query.width),
+        scaled(context, query.height),
+    ))
+}
 
-                    texture = context
-                        .texture_creator
-                        .load_texture_bytes(context.images.get(&img_path).unwrap())
+pub fn font_for<'a>(
+    context: &RendererContext<'a>,
+    style: &Style,
+) -> Rc<RefCell<sdl2::ttf::Font<'a, 'a>>> {
+    if style.monospace {
+        context.mono_font.clone()
+    } else {
+        context.font.clone()
+    }
+}
+
+pub fn text_color(context: &RendererContext, style: &Style) -> Color {
+    if style.highlight {
+        context.color_scheme.mark_text
+    } else if style.visited {
+        context.color_scheme.visited_link
+    } else if style.href.is_some() {
+        context.color_scheme.link
+    } else if style.heading {
+        context.color_scheme.heading
+    } else {
+        context.color_scheme.text
+    }
+}
+
+// Convert CSS pixels of the document to output pixels at the current zoom level
+pub fn scaled(context: &RendererContext, length: u32) -> u32 {
+    (length as f32 * context.scaling_factor as f32 * context.zoom).round() as u32
+}
+
+pub fn scaled_signed(context: &RendererContext, length: i32) -> i32 {
+    (length as f32 * context.scaling_factor as f32 * context.zoom).round() as i32
+}
+
+// The next zoom level in `steps` direction, like the levels browsers offer
+pub fn zoom_level(zoom: f32, steps: i32) -> f32 {
+    let levels = [
+        0.3, 0.5, 0.67, 0.8, 0.9, 1.0, 1.1, 1.25, 1.5, 1.75, 2.0, 2.5, 3.0, 4.0, 5.0,
+    ];
+    let current = levels
+        .iter()
+        .position(|&level| level >= zoom - 0.001)
+        .unwrap_or(levels.len() - 1) as i32;
+    levels[(current + steps).clamp(0, levels.len() as i32 - 1) as usize]
+}
+
+// Width of `text` when rendered at `font_size`
+pub fn text_width(font: &sdl2::ttf::Font, text: &str, font_size: u32) -> u32 {
+    font_runs(font, text)
+        .into_iter()
+        .map(|(range, fallback)| {
+            let text = &text[range];
+            if fallback {
+                if let Some(width) = shaped_width(text, font_size, false) {
+                    return width;
+                }
+            }
+            let (width, height) = font.size_of(text).unwrap();
+            (width as f32 * font_size as f32 / height as f32).ceil() as u32
+        })
+        .sum()
+}
+
+// Width of `text` including the letter and word spacing of `style`
+pub fn spaced_text_width(
+    context: &RendererContext,
+    style: &Style,
+    font: &sdl2::ttf::Font,
+    text: &str,
+    font_size: u32,
+) -> u32 {
+    // Letter spacing goes after each grapheme so combining marks don't pull text back
+    let spacing = scaled_signed(context, style.letter_spacing)
+        * text.graphemes(true).count() as i32
+        + scaled_signed(context, style.word_spacing) * text.matches(' ').count() as i32;
+    (text_width(font, text, font_size) as i32 + spacing).max(0) as u32
+}
+
+// Place an atomic inline box, such as a form control, on the current line
+pub fn place_inline_box(
+    context: &mut RendererContext,
+    width: u32,
+    height: u32,
+    control: usize,
+    focus: Option<usize>,
+) {
+    let space = if context.pending_space && !context.line.is_empty() {
+        let font = font_for(context, &context.style);
+        let width = spaced_text_width(
+            context,
+            &context.style,
+            &font.borrow(),
+            " ",
+            scaled(context, context.style.font_size),
+        );
+        width
+    } else {
+        0
+    };
+    context.pending_space = false;
+    let space = if context.style.white_space.wraps()
+        && !context.line.is_empty()
+        && context.indices.0 + space + width > line_right(context)
+    {
+        wrap_line(context);
+        0
+    } else {
+        space
+    };
+    let style = Style {
+        focus,
+        ..context.style.clone()
+    };
+    context.line.push(LineItem {
+        text: String::new(),
+        x: context.indices.0 + space,
+        width,
+        style,
+        control: Some((control, height)),
+    });
+    context.indices.0 += space + width;
+}
+
+// Left edge of the content box lines are currently laid out in
+fn line_left(context: &RendererContext) -> u32 {
+    match context.blocks.last() {
+        Some(block) => {
+            block.left
+                + scaled(
+                    context,
+                    block.box_style.border[3] + block.box_style.padding[3],
+                )
+        }
+        None => 0,
+    }
+}
+
+fn line_right(context: &RendererContext) -> u32 {
+    let right = match context.blocks.last() {
+        Some(block) => block.right.saturating_sub(scaled(
+            context,
+            block.box_style.border[1] + block.box_style.padding[1],
+        )),
+        None => context.canvas.borrow().output_size().unwrap().0,
+    };
+    right.max(line_left(context))
+}
+
+fn collapse_margin(context: &mut RendererContext, margin: i32) {
+    let (positive, negative) = context.pending_margin;
+    context.pending_margin = (positive.max(margin), negative.min(margin));
+}
+
+// Position below a collapsed margin, which can't pull content above the top of the innermost
+// block whose top is known
+fn after_margin(blocks: &[BlockBox], y: u32, (positive, negative): (i32, i32)) -> u32 {
+    let floor = blocks.iter().rev().find_map(|block| block.top).unwrap_or(0);
+    (y as i32 + positive + negative).max(floor as i32) as u32
+}
+
+// Place the collapsed margin before content, which also fixes the top of blocks opened above it
+fn flush_margin(context: &mut RendererContext) {
+    let margin = std::mem::take(&mut context.pending_margin);
+    context.indices.1 = after_margin(&context.blocks, context.indices.1, margin);
+    for block in context.blocks.iter_mut().rev() {
+        if block.top.is_some() {
+            break;
+        }
+        block.top = Some(context.indices.1);
+    }
+}
+
+fn open_block(context: &mut RendererContext, box_style: BoxStyle, style: &Style) {
+    finish_line(context);
+    collapse_margin(context, scaled_signed(context, box_style.margin[0]));
+    let left = (line_left(context) as i32 + scaled_signed(context, box_style.margin[3])).max(0);
+    let right =
+        (line_right(context) as i32 - scaled_signed(context, box_style.margin[1])).max(left);
+    let left = left as u32;
+    let top_edge = scaled(context, box_style.border[0] + box_style.padding[0]);
+    let direction = match box_style.unicode_bidi {
+        UnicodeBidi::IsolateAuto => None,
+        _ => Some(style.direction),
+    };
+    context.block_count += 1;
+    context.blocks.push(BlockBox {
+        id: context.block_count,
+        top: None,
+        left,
+        right: right as u32,
+        box_style,
+        direction,
+        text_align: style.text_align,
+        display_index: context.display_list.len(),
+    });
+    // Borders and padding separate the margins of the block from those of its children
+    if top_edge > 0 {
+        flush_margin(context);
+        context.indices.1 += top_edge;
+    }
+    context.indices.0 =
+        (line_left(context) as i32 + scaled_signed(context, style.text_indent)).max(0) as u32;
+}
+
+fn close_block(context: &mut RendererContext) {
+    finish_line(context);
+    let bottom_edge = {
+        let box_style = &context.blocks.last().unwrap().box_style;
+        scaled(context, box_style.border[2] + box_style.padding[2])
+    };
+    if bottom_edge > 0 {
+        flush_margin(context);
+        context.indices.1 += bottom_edge;
+    }
+    let block = context.blocks.pop().unwrap();
+    let box_style = &block.box_style;
+    let top = block.top.unwrap_or(context.indices.1);
+    let (width, height) = (
+        block.right - block.left,
+        context.indices.1.saturating_sub(top),
+    );
+    let mut items = Vec::new();
+    if let (Some(color), true) = (box_style.background, height > 0) {
+        items.push(DisplayItem::Rect {
+            x: block.left as i32,
+            y: top as i32,
+            width,
+            height,
+            color,
+        });
+    }
+    let border = box_style.border.map(|b| scaled(context, b));
+    context.debug.boxes.push(LayoutBox {
+        x: block.left as i32,
+        y: top as i32,
+        width,
+        height,
+        margin: box_style.margin.map(|m| scaled_signed(context, m)),
+        border,
+        padding: box_style.padding.map(|p| scaled(context, p)),
+    });
+    let sides = [
+        (block.left, top, width, border[0]),
+        (block.right - border[1].min(width), top, border[1], height),
+        (
+            block.left,
+            context.indices.1 - border[2].min(height),
+            width,
+            border[2],
+        ),
+        (block.left, top, border[3], height),
+    ];
+    for (side, (x, y, width, height)) in sides.iter().enumerate() {
+        if *width > 0 && *height > 0 {
+            items.push(DisplayItem::Rect {
+                x: *x as i32,
+                y: *y as i32,
+                width: *width,
+                height: *height,
+                color: box_style.border_color[side]
+                    .unwrap_or_else(|| text_color(context, &context.style)),
+            });
+        }
+    }
+    context
+        .display_list
+        .splice(block.display_index..block.display_index, items);
+    collapse_margin(context, scaled_signed(context, box_style.margin[2]));
+    context.indices.0 = line_left(context);
+}
+
+// End the paragraph with the pending line items, lay out its lines and move down below them
+fn finish_line(context: &mut RendererContext) {
+    let mut lines = std::mem::take(&mut context.paragraph);
+    let anchors = std::mem::take(&mut context.paragraph_anchors);
+    let wrapped = lines.len();
+    let line = std::mem::take(&mut context.line);
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    context.pending_space = false;
+    context.indices.0 = line_left(context);
+    if lines.is_empty() {
+        return;
+    }
+    flush_margin(context);
+    let (base, text_align) = match context.blocks.last() {
+        Some(block) => (block.direction, block.text_align),
+        None => (Some(Direction::Ltr), TextAlign::Start),
+    };
+    let (lines, rtl) = reorder_paragraph(context, lines, base, line_left(context));
+    for (i, line) in lines.into_iter().enumerate() {
+        for (anchor, _) in anchors.iter().filter(|(_, line)| *line == i) {
+            let y = context.indices.1;
+            context.anchors.entry(anchor.clone()).or_insert(y);
+        }
+        lay_out_line(context, line, i < wrapped, text_align, rtl);
+    }
+}
+
+// Move on to the next line because the current one is full, which justified text is stretched to
+fn wrap_line(context: &mut RendererContext) {
+    let line = std::mem::take(&mut context.line);
+    context.paragraph.push(line);
+    context.pending_space = false;
+    context.indices.0 = line_left(context);
+}
+
+fn lay_out_line(
+    context: &mut RendererContext,
+    line: Vec<LineItem>,
+    wrapped: bool,
+    text_align: TextAlign,
+    rtl: bool,
+) {
+    let text_align = match (text_align, rtl) {
+        (TextAlign::Start, false) | (TextAlign::End, true) => TextAlign::Left,
+        (TextAlign::Start, true) | (TextAlign::End, false) => TextAlign::Right,
+        (TextAlign::Justify, _) if !wrapped => TextAlign::Left,
+        (text_align, _) => text_align,
+    };
+    let line = align_line(context, line, text_align, line_right(context));
+    // Align the baselines of all items, then fit the line around the shifted items. Each item
+    // takes up its line height, with the leading split above and below the text, and controls
+    // sit on the baseline.
+    let heights: Vec<u32> = line
+        .iter()
+        .map(|item| match item.control {
+            Some((_, height)) => height,
+            None => scaled(context, item.style.font_size),
+        })
+        .collect();
+    let line_heights: Vec<u32> = line
+        .iter()
+        .zip(&heights)
+        .map(|(item, &height)| match item.control {
+            Some(_) => height,
+            None => line_height(context, &item.style),
+        })
+        .collect();
+    let ascents: Vec<i32> = line
+        .iter()
+        .zip(&heights)
+        .map(|(item, &height)| {
+            if item.control.is_some() {
+                return height as i32;
+            }
+            let font = font_for(context, &item.style);
+            let font = font.borrow();
+            font.ascent() * height as i32 / font.height()
+        })
+        .collect();
+    // Distance from the top of each item's line box to its baseline
+    let above: Vec<i32> = ascents
+        .iter()
+        .zip(heights.iter().zip(&line_heights))
+        .map(|(ascent, (&height, &line_height))| ascent + (line_height as i32 - height as i32) / 2)
+        .collect();
+    let baseline = line
+        .iter()
+        .zip(&above)
+        .map(|(item, above)| above - scaled_signed(context, item.style.baseline_shift))
+        .max()
+        .unwrap();
+    let height = line
+        .iter()
+        .zip(&above)
+        .zip(&line_heights)
+        .map(|((item, above), &line_height)| {
+            baseline - above
+                + scaled_signed(context, item.style.baseline_shift)
+                + line_height as i32
+        })
+        .max()
+        .unwrap()
+        .max(0) as u32;
+    let start = line.iter().map(|item| item.x).min().unwrap();
+    let end = line.iter().map(|item| item.x + item.width).max().unwrap();
+    context.debug.baselines.push((
+        start as i32,
+        context.indices.1 as i32 + baseline,
+        end - start,
+    ));
+    for (item, ascent) in line.into_iter().zip(ascents) {
+        let (x, y) = (
+            item.x as i32,
+            context.indices.1 as i32 + baseline - ascent
+                + scaled_signed(context, item.style.baseline_shift),
+        );
+        if let Some((index, height)) = item.control {
+            context.hit_map.push(HitRect {
+                x,
+                y,
+                width: item.width,
+                height,
+                href: None,
+                text: edits_text(&context.controls[index]),
+                focus: item.style.focus,
+            });
+            context.display_list.push(DisplayItem::Control {
+                x,
+                y,
+                width: item.width,
+                height,
+                index,
+            });
+            continue;
+        }
+        let font_size = scaled(context, item.style.font_size);
+        let font = font_for(context, &item.style);
+        font.borrow_mut().set_style(item.style.font_style);
+        let width = spaced_text_width(context, &item.style, &font.borrow(), &item.text, font_size);
+        font.borrow_mut().set_style(FontStyle::NORMAL);
+        if item.style.highlight {
+            context.display_list.push(DisplayItem::Rect {
+                x,
+                y,
+                width,
+                height: font_size,
+                color: context.color_scheme.mark,
+            });
+        }
+        context.hit_map.push(HitRect {
+            x,
+            y,
+            width,
+            height: font_size,
+            href: item.style.href.clone(),
+            text: true,
+            focus: item.style.focus,
+        });
+        context.display_list.push(DisplayItem::Text {
+            x,
+            y,
+            width,
+            height: font_size,
+            text: item.text,
+            style: item.style,
+            block: context.blocks.last().map(|block| block.id).unwrap_or(0),
+        });
+    }
+    context.indices.1 += height;
+}
+
+// Height of a line of text in `style`, in output pixels
+fn line_height(context: &RendererContext, style: &Style) -> u32 {
+    let font_size = scaled(context, style.font_size);
+    match style.line_height {
+        LineHeight::Normal => font_size,
+        LineHeight::Number(factor) => (font_size as f32 * factor).round() as u32,
+        LineHeight::Length(length) => scaled(context, length),
+    }
+}
+
+// Forced line break, which also takes up space when the line is empty
+fn line_break(context: &mut RendererContext, height: u32) {
+    if context.line.is_empty() {
+        finish_line(context);
+        flush_margin(context);
+        context.indices.1 += height;
+    }
+    finish_line(context);
+}
+
+pub async fn render_document(handle: &Handle, context: &mut RendererContext<'_>) {
+    context.hit_map.clear();
+    context.anchors.clear();
+    context.focusables.clear();
+    context.forms.clear();
+    context.control_count = 0;
+    context.line.clear();
+    context.paragraph.clear();
+    context.paragraph_anchors.clear();
+    context.display_list.clear();
+    context.blocks.clear();
+    context.pending_margin = (0, 0);
+    context.block_count = 0;
+    context.embedding_count = 0;
+    context.indices = (0, 0);
+    context.debug.boxes.clear();
+    context.debug.baselines.clear();
+    let started = Instant::now();
+    render(handle, "", context).await;
+    finish_line(context);
+    flush_margin(context);
+    context.debug.layout_time = started.elapsed();
+    let width = context
+        .display_list
+        .iter()
+        .map(|item| match *item {
+            DisplayItem::Rect { x, width, .. }
+            | DisplayItem::Text { x, width, .. }
+            | DisplayItem::Image { x, width, .. }
+            | DisplayItem::Control { x, width, .. } => (x + width as i32).max(0) as u32,
+        })
+        .max()
+        .unwrap_or(0);
+    context.document_size = (width, context.indices.1);
+    if context.focus >= Some(context.focusables.len()) {
+        context.focus = None;
+    }
+    clamp_viewport(context);
+    paint(context);
+}
+
+// Keep the viewport from scrolling past the edges of the document
+pub fn clamp_viewport(context: &mut RendererContext) {
+    let (screen_width, screen_height) = context.canvas.borrow().output_size().unwrap();
+    let (width, height) = context.document_size;
+    let min_x = -(width.saturating_sub(screen_width) as i32);
+    let min_y = -(height.saturating_sub(screen_height) as i32);
+    context.viewport = (
+        context.viewport.0.clamp(min_x, 0),
+        context.viewport.1.clamp(min_y, 0),
+    );
+}
+
+// Scroll towards the end of the document by a distance in output pixels,
+// returns whether the viewport moved
+pub fn scroll_by(context: &mut RendererContext, dx: i32, dy: i32) -> bool {
+    let previous = context.viewport;
+    context.viewport.0 = context.viewport.0.saturating_sub(dx);
+    context.viewport.1 = context.viewport.1.saturating_sub(dy);
+    clamp_viewport(context);
+    context.viewport != previous
+}
+
+// Start or speed up kinetic scrolling so that it covers the given distance before stopping
+pub fn fling(context: &mut RendererContext, dx: f32, dy: f32) {
+    context.scroll_velocity.0 += dx * SCROLL_FRICTION;
+    context.scroll_velocity.1 += dy * SCROLL_FRICTION;
+}
+
+// Advance kinetic scrolling by `dt` seconds, returns whether the viewport moved
+pub fn step_scroll(context: &mut RendererContext, dt: f32) -> bool {
+    let (vx, vy) = context.scroll_velocity;
+    if vx == 0.0 && vy == 0.0 {
+        return false;
+    }
+    // Distance covered by the exponentially decaying velocity during `dt`
+    let decay = (-SCROLL_FRICTION * dt).exp();
+    let dx = vx * (1.0 - decay) / SCROLL_FRICTION + context.scroll_remainder.0;
+    let dy = vy * (1.0 - decay) / SCROLL_FRICTION + context.scroll_remainder.1;
+    context.scroll_remainder = (dx.fract(), dy.fract());
+    let slow = |v: f32| if v.abs() < 5.0 { 0.0 } else { v };
+    context.scroll_velocity = (slow(vx * decay), slow(vy * decay));
+    let previous = context.viewport;
+    let moved = scroll_by(context, dx.trunc() as i32, dy.trunc() as i32);
+    // Stop at the edges of the document instead of pushing against them
+    if context.viewport.0 == previous.0 && dx.trunc() != 0.0 {
+        context.scroll_velocity.0 = 0.0;
+    }
+    if context.viewport.1 == previous.1 && dy.trunc() != 0.0 {
+        context.scroll_velocity.1 = 0.0;
+    }
+    moved
+}
+
+pub fn stop_scroll(context: &mut RendererContext) {
+    context.scroll_velocity = (0.0, 0.0);
+    context.scroll_remainder = (0.0, 0.0);
+}
+
+// Draw a single run of text at screen coordinates and return its width
+pub fn draw_text(
+    context: &RendererContext,
+    text: &str,
+    style: &Style,
+    font_size: u32,
+    color: Color,
+    x: i32,
+    y: i32,
+) -> u32 {
+    if text.is_empty() {
+        return 0;
+    }
+    let rtl = style.direction == Direction::Rtl;
+    let letter_spacing = scaled_signed(context, style.letter_spacing);
+    let word_spacing = scaled_signed(context, style.word_spacing);
+    if letter_spacing != 0 || word_spacing != 0 {
+        // Spaced text is drawn a letter or a word at a time
+        let unspaced = Style {
+            letter_spacing: 0,
+            word_spacing: 0,
+            ..style.clone()
+        };
+        let mut pieces: Vec<&str> = if letter_spacing != 0 {
+            text.char_indices()
+                .map(|(i, c)| &text[i..i + c.len_utf8()])
+                .collect()
+        } else {
+            text.split_inclusive(' ').collect()
+        };
+        if rtl {
+            pieces.reverse();
+        }
+        let mut width = 0;
+        for piece in pieces {
+            width += draw_text(context, piece, &unspaced, font_size, color, x + width, y) as i32
+                + letter_spacing * piece.chars().count() as i32
+                + word_spacing * piece.matches(' ').count() as i32;
+        }
+        return width.max(0) as u32;
+    }
+    let font = font_for(context, style);
+    font.borrow_mut().set_style(style.font_style);
+    let mut runs = font_runs(&font.borrow(), text);
+    if rtl {
+        runs.reverse();
+    }
+    let mut width = 0;
+    for (range, fallback) in runs {
+        let text = &text[range];
+        let shaped = if fallback {
+            render_shaped(text, font_size, color, rtl)
+        } else {
+            None
+        };
+        let lines = shaped.is_some();
+        let surface = match shaped {
+            Some(surface) => surface,
+            None if rtl => font
+                .borrow()
+                .render(&visual_order(text))
+                .blended(color)
+                .unwrap(),
+            None => font.borrow().render(text).blended(color).unwrap(),
+        };
+        let texture = context
+            .texture_creator
+            .create_texture_from_surface(&surface)
+            .map_err(|e| e.to_string())
+            .unwrap();
+        let run_width =
+            (surface.width() as f32 * font_size as f32 / surface.height() as f32).ceil() as u32;
+        let run_x = x + width as i32;
+        let mut canvas = context.canvas.borrow_mut();
+        canvas
+            .copy(&texture, None, rect!(run_x, y, run_width, font_size))
+            .unwrap();
+        // Fallback fonts are drawn without SDL_ttf, so their lines are drawn here
+        if lines {
+            let thickness = (font_size / 16).max(1);
+            canvas.set_draw_color(color);
+            if style.font_style.contains(FontStyle::UNDERLINE) {
+                canvas
+                    .fill_rect(rect!(
+                        run_x,
+                        y + (font_size * 7 / 8) as i32,
+                        run_width,
+                        thickness
+                    ))
+                    .unwrap();
+            }
+            if style.font_style.contains(FontStyle::STRIKETHROUGH) {
+                canvas
+                    .fill_rect(rect!(
+                        run_x,
+                        y + (font_size / 2) as i32,
+                        run_width,
+                        thickness
+                    ))
+                    .unwrap();
+            }
+        }
+        width += run_width;
+    }
+    font.borrow_mut().set_style(FontStyle::NORMAL);
+    width
+}
+
+// Draw the display list, skipping everything outside the visible part of the document
+pub fn paint(context: &RendererContext) {
+    let started = Instant::now();
+    let screen_height = context.canvas.borrow().output_size().unwrap().1 as i32;
+    context
+        .canvas
+        .borrow_mut()
+        .set_draw_color(context.color_scheme.background);
+    context.canvas.borrow_mut().clear();
+    for (i, item) in context.display_list.iter().enumerate() {
+        let (x, y, width, height) = match *item {
+            DisplayItem::Rect {
+                x,
+                y,
+                width,
+                height,
+                ..
+            }
+            | DisplayItem::Text {
+                x,
+                y,
+                width,
+                height,
+                ..
+            }
+            | DisplayItem::Image {
+                x,
+                y,
+                width,
+                height,
+                ..
+            }
+            | DisplayItem::Control {
+                x,
+                y,
+                width,
+                height,
+                ..
+            } => (
+                x + context.viewport.0,
+                y + context.viewport.1,
+                width,
+                height,
+            ),
+        };
+        if y + (height as i32) < 0 || y > screen_height || width == 0 {
+            continue;
+        }
+        match item {
+            DisplayItem::Rect { color, .. } => {
+                let mut canvas = context.canvas.borrow_mut();
+                canvas.set_draw_color(*color);
+                canvas.fill_rect(rect!(x, y, width, height)).unwrap();
+            }
+            DisplayItem::Text { text, style, .. } => {
+                if let (true, Some(color)) = (style.monospace, context.color_scheme.code_background)
+                {
+                    let mut canvas = context.canvas.borrow_mut();
+                    canvas.set_draw_color(color);
+                    canvas.fill_rect(rect!(x, y, width, height)).unwrap();
+                }
+                let mut ranges = match_ranges(context, i);
+                if let Some((from, to)) = selected_range(context, i) {
+                    ranges.push((from, to, context.color_scheme.selection));
+                }
+                for (from, to, color) in ranges {
+                    let start = prefix_width(context, style, text, from);
+                    let end = prefix_width(context, style, text, to);
+                    let mut canvas = context.canvas.borrow_mut();
+                    canvas.set_draw_color(color);
+                    canvas
+                        .fill_rect(rect!(
+                            x + start as i32,
+                            y,
+                            end.saturating_sub(start),
+                            height
+                        ))
                         .unwrap();
-                    let query = texture.query();
-                    width = query.width * context.scaling_factor;
-                    height = query.height * context.scaling_factor;
                 }
-                if let Ok(surface) = Surface::from_file(Path::new(&img_path)) {
-                    width = surface.width() * context.scaling_factor;
-                    height = surface.height() * context.scaling_factor;
-                    texture = context
-                        .texture_creator
-                        .create_texture_from_surface(&surface)
+                let color = text_color(context, style);
+                draw_text(context, text, style, height, color, x, y);
+            }
+            DisplayItem::Image { src, .. } => {
+                if let Ok(texture) = context
+                    .texture_creator
+                    .load_texture_bytes(&context.images[src])
+                {
+                    context
+                        .canvas
+                        .borrow_mut()
+                        .copy(&texture, None, rect!(x, y, width, height))
                         .unwrap();
-                } else {
-                    println!("Couldn't load image: {}", img_path);
                 }
-                context.hit_map.push((
-                    context.indices.0 as i32 + context.viewport.0,
-                    context.indices.1 as i32 + context.viewport.1,
-                    width,
-                    height,
-                    || println!("Hello, I'm an image"),
-                ));
-                context
-                    .canvas
-                    .borrow_mut()
-                    .copy(
-                        &texture,
-                        None,
-                        rect!(
-                            context.indices.0 as i32 + context.viewport.0,
-                            context.indices.1 as i32 + context.viewport.1,
-                            width,
-                            height
-                        ),
-                    )
-                    .unwrap();
-                context.indices.1 += height;
+            }
+            DisplayItem::Control { index, .. } => {
+                paint_control(context, *index, x, y, width, height);
             }
         }
-        NodeData::ProcessingInstruction { .. } => unreachable!(),
-        _ => {}
     }
-    for child in node.children.borrow().iter() {
-        render(indent + 1, child, next_tag_name, context).await;
+    context.debug.paint_time.set(started.elapsed());
+    paint_debug_overlays(context);
+    paint_focus_ring(context);
+    paint_scrollbars(context);
+    if let Some(link) = &context.hovered_link {
+        paint_status(context, link);
+    }
+    if let Some(find) = &context.find {
+        paint_find_bar(context, find);
     }
 }
 
@@ -327,3 +1359,38 @@ pub fn print_dom(indent: usize, handle: &Handle) {
         print_dom(indent + 4, child);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(top: Option<u32>, border: u32) -> BlockBox {
+        BlockBox {
+            id: 0,
+            top,
+            left: 0,
+            right: 100,
+            box_style: BoxStyle {
+                display: Display::Block,
+                margin: [0; 4],
+                padding: [0; 4],
+                border: [border; 4],
+                border_color: [None; 4],
+                background: None,
+                unicode_bidi: UnicodeBidi::Normal,
+            },
+            direction: Some(Direction::Ltr),
+            text_align: TextAlign::Start,
+            display_index: 0,
+        }
+    }
+
+    // <div style="border:1px solid"><p style="margin-top:-100px">x</p></div>
+    #[test]
+    fn negative_margin_stops_at_bordered_box() {
+        let blocks = [block(Some(0), 0), block(Some(20), 1), block(None, 0)];
+        assert_eq!(after_margin(&blocks, 21, (0, -100)), 20);
+        assert_eq!(after_margin(&blocks, 21, (8, -4)), 25);
+        assert_eq!(after_margin(&[], 10, (0, -100)), 0);
+    }
+}
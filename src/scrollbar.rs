@@ -0,0 +1,145 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use crate::renderer::RendererContext;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    Vertical,
+    Horizontal,
+}
+
+// Thickness of a scrollbar and shortest its thumb gets, in CSS pixels
+static SCROLLBAR_WIDTH: u32 = 10;
+static MIN_THUMB_LENGTH: u32 = 20;
+
+// Track and thumb of a scrollbar in screen coordinates, None when the document fits
+pub fn geometry(context: &RendererContext, axis: Axis) -> Option<(Rect, Rect)> {
+    let (screen_width, screen_height) = context.canvas.borrow().output_size().unwrap();
+    let (width, height) = context.document_size;
+    let thickness = SCROLLBAR_WIDTH * context.scaling_factor;
+    let vertical = height > screen_height;
+    let horizontal = width > screen_width;
+    // The bars leave the bottom right corner free when both are shown
+    let (document, screen, track, offset) = match axis {
+        Axis::Vertical if vertical => (
+            height,
+            screen_height,
+            screen_height - if horizontal { thickness } else { 0 },
+            -context.viewport.1,
+        ),
+        Axis::Horizontal if horizontal => (
+            width,
+            screen_width,
+            screen_width - if vertical { thickness } else { 0 },
+            -context.viewport.0,
+        ),
+        _ => return None,
+    };
+    let thumb = (track as u64 * screen as u64 / document as u64) as u32;
+    let thumb = thumb
+        .max(MIN_THUMB_LENGTH * context.scaling_factor)
+        .min(track);
+    let position = (offset as u64 * (track - thumb) as u64 / (document - screen) as u64) as i32;
+    Some(match axis {
+        Axis::Vertical => {
+            let x = (screen_width - thickness) as i32;
+            (
+                Rect::new(x, 0, thickness, track),
+                Rect::new(x, position, thickness, thumb),
+            )
+        }
+        Axis::Horizontal => {
+            let y = (screen_height - thickness) as i32;
+            (
+                Rect::new(0, y, track, thickness),
+                Rect::new(position, y, thumb, thickness),
+            )
+        }
+    })
+}
+
+pub fn paint_scrollbars(context: &RendererContext) {
+    let scheme = &context.color_scheme;
+    // The track is a faint tint of the text colour over the background
+    let track_color = Color::RGB(
+        ((scheme.background.r as u32 * 9 + scheme.text.r as u32) / 10) as u8,
+        ((scheme.background.g as u32 * 9 + scheme.text.g as u32) / 10) as u8,
+        ((scheme.background.b as u32 * 9 + scheme.text.b as u32) / 10) as u8,
+    );
+    for axis in [Axis::Vertical, Axis::Horizontal] {
+        if let Some((track, thumb)) = geometry(context, axis) {
+            let mut canvas = context.canvas.borrow_mut();
+            canvas.set_draw_color(track_color);
+            canvas.fill_rect(track).unwrap();
+            canvas.set_draw_color(scheme.scrollbar);
+            canvas.fill_rect(thumb).unwrap();
+        }
+    }
+}
+
+// The scrollbar under a point in screen coordinates and whether the point is on its thumb
+pub fn scrollbar_at(context: &RendererContext, x: i32, y: i32) -> Option<(Axis, bool)> {
+    [Axis::Vertical, Axis::Horizontal]
+        .iter()
+        .find_map(|&axis| match geometry(context, axis) {
+            Some((track, thumb)) if track.contains_point((x, y)) => {
+                Some((axis, thumb.contains_point((x, y))))
+            }
+            _ => None,
+        })
+}
+
+// Distance from the start of the thumb to a point along the axis
+pub fn thumb_offset(context: &RendererContext, axis: Axis, x: i32, y: i32) -> i32 {
+    match (geometry(context, axis), axis) {
+        (Some((_, thumb)), Axis::Vertical) => y - thumb.y(),
+        (Some((_, thumb)), Axis::Horizontal) => x - thumb.x(),
+        _ => 0,
+    }
+}
+
+// Move the viewport so that the thumb starts `grab` pixels before the pointer
+pub fn drag_thumb(context: &mut RendererContext, axis: Axis, x: i32, y: i32, grab: i32) {
+    let (track, thumb) = match geometry(context, axis) {
+        Some(geometry) => geometry,
+        None => return,
+    };
+    let (screen_width, screen_height) = context.canvas.borrow().output_size().unwrap();
+    let (pointer, track_length, thumb_length, scrollable) = match axis {
+        Axis::Vertical => (
+            y,
+            track.height(),
+            thumb.height(),
+            context.document_size.1 - screen_height,
+        ),
+        Axis::Horizontal => (
+            x,
+            track.width(),
+            thumb.width(),
+            context.document_size.0 - screen_width,
+        ),
+    };
+    let free = (track_length - thumb_length).max(1) as i64;
+    let position = (pointer - grab).clamp(0, free as i32) as i64;
+    let offset = -((position * scrollable as i64 / free) as i32);
+    match axis {
+        Axis::Vertical => context.viewport.1 = offset,
+        Axis::Horizontal => context.viewport.0 = offset,
+    }
+}
+
+// Scroll one page towards a click on the track outside the thumb
+pub fn page_towards(context: &RendererContext, axis: Axis, x: i32, y: i32) -> (i32, i32) {
+    let (screen_width, screen_height) = context.canvas.borrow().output_size().unwrap();
+    let thumb = match geometry(context, axis) {
+        Some((_, thumb)) => thumb,
+        None => return (0, 0),
+    };
+    match axis {
+        Axis::Vertical if y < thumb.y() => (0, -(screen_height as i32)),
+        Axis::Vertical => (0, screen_height as i32),
+        Axis::Horizontal if x < thumb.x() => (-(screen_width as i32), 0),
+        Axis::Horizontal => (screen_width as i32, 0),
+    }
+}
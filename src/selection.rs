@@ -0,0 +1,177 @@
+use crate::renderer::{font_for, scaled, spaced_text_width, DisplayItem, RendererContext};
+use crate::style::Style;
+
+// A character boundary inside a text item of the display list
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TextPosition {
+    pub item: usize,
+    pub offset: usize,
+}
+
+// Width of the first `offset` bytes of a text item
+pub fn prefix_width(context: &RendererContext, style: &Style, text: &str, offset: usize) -> u32 {
+    if offset == 0 {
+        return 0;
+    }
+    let font = font_for(context, style);
+    font.borrow_mut().set_style(style.font_style);
+    let width = spaced_text_width(
+        context,
+        style,
+        &font.borrow(),
+        &text[..offset],
+        scaled(context, style.font_size),
+    );
+    font.borrow_mut().set_style(sdl2::ttf::FontStyle::NORMAL);
+    width
+}
+
+// The text position closest to a point in document coordinates, preferring the line under it
+pub fn position_at(context: &RendererContext, x: i32, y: i32) -> Option<TextPosition> {
+    let distance = |start: i32, length: u32, point: i32| {
+        if point < start {
+            start - point
+        } else {
+            (point - start - length as i32).max(0)
+        }
+    };
+    let (item, _) = context
+        .display_list
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| match *item {
+            DisplayItem::Text {
+                x: item_x,
+                y: item_y,
+                width,
+                height,
+                ..
+            } => Some((i, (distance(item_y, height, y), distance(item_x, width, x)))),
+            _ => None,
+        })
+        .min_by_key(|(_, distance)| *distance)?;
+    if let DisplayItem::Text {
+        x: item_x,
+        ref text,
+        ref style,
+        ..
+    } = context.display_list[item]
+    {
+        let offset = text
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain(Some(text.len()))
+            .min_by_key(|&offset| {
+                (item_x + prefix_width(context, style, text, offset) as i32 - x).abs()
+            })
+            .unwrap();
+        return Some(TextPosition { item, offset });
+    }
+    None
+}
+
+// Selection covering the word around `position`
+pub fn word_at(context: &RendererContext, position: TextPosition) -> (TextPosition, TextPosition) {
+    let text = item_text(context, position.item);
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let start = text[..position.offset]
+        .rfind(|c: char| !is_word(c))
+        .map(|i| i + text[i..].chars().next().unwrap().len_utf8())
+        .unwrap_or(0);
+    let end = text[position.offset..]
+        .find(|c: char| !is_word(c))
+        .map(|i| i + position.offset)
+        .unwrap_or(text.len());
+    (
+        TextPosition {
+            item: position.item,
+            offset: start,
+        },
+        TextPosition {
+            item: position.item,
+            offset: end,
+        },
+    )
+}
+
+// Selection covering every text item laid out in the same block as `position`
+pub fn paragraph_at(
+    context: &RendererContext,
+    position: TextPosition,
+) -> (TextPosition, TextPosition) {
+    let block = item_block(context, position.item);
+    let items: Vec<usize> = (0..context.display_list.len())
+        .filter(|&i| item_block(context, i) == block)
+        .collect();
+    let last = *items.last().unwrap();
+    (
+        TextPosition {
+            item: items[0],
+            offset: 0,
+        },
+        TextPosition {
+            item: last,
+            offset: item_text(context, last).len(),
+        },
+    )
+}
+
+// The selected range of the text item at `index`, if any of it is selected
+pub fn selected_range(context: &RendererContext, index: usize) -> Option<(usize, usize)> {
+    let (anchor, focus) = context.selection?;
+    let (start, end) = (anchor.min(focus), anchor.max(focus));
+    if index < start.item || index > end.item {
+        return None;
+    }
+    let from = if index == start.item { start.offset } else { 0 };
+    let to = if index == end.item {
+        end.offset
+    } else {
+        item_text(context, index).len()
+    };
+    Some((from, to)).filter(|(from, to)| from < to)
+}
+
+// The selected text, with line breaks between blocks and spaces between lines
+pub fn selected_text(context: &RendererContext) -> String {
+    let mut selected = String::new();
+    let mut previous: Option<(i32, i32, usize)> = None;
+    for (i, item) in context.display_list.iter().enumerate() {
+        if let DisplayItem::Text {
+            x,
+            y,
+            width,
+            ref text,
+            block,
+            ..
+        } = *item
+        {
+            if let Some((from, to)) = selected_range(context, i) {
+                match previous {
+                    Some((_, _, previous_block)) if previous_block != block => selected.push('\n'),
+                    Some((previous_y, previous_end, _)) if previous_y != y || previous_end < x => {
+                        selected.push(' ')
+                    }
+                    _ => {}
+                }
+                selected.push_str(&text[from..to]);
+                previous = Some((y, x + width as i32, block));
+            }
+        }
+    }
+    selected
+}
+
+fn item_text<'a>(context: &'a RendererContext, index: usize) -> &'a str {
+    match context.display_list[index] {
+        DisplayItem::Text { ref text, .. } => text,
+        _ => "",
+    }
+}
+
+fn item_block(context: &RendererContext, index: usize) -> Option<usize> {
+    match context.display_list[index] {
+        DisplayItem::Text { block, .. } => Some(block),
+        _ => None,
+    }
+}
@@ -0,0 +1,358 @@
+use std::ops::Range;
+
+use ab_glyph::{point, Font, FontRef, GlyphId, PxScale};
+use rustybuzz::ttf_parser::colr::{ClipBox, CompositeMode, Paint, Painter};
+use rustybuzz::ttf_parser::{self, RasterImageFormat, RgbaColor, Transform};
+use rustybuzz::{Direction, Face, UnicodeBuffer};
+use sdl2::image::ImageRWops;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+use sdl2::rwops::RWops;
+use sdl2::surface::Surface;
+use unicode_script::{Script, UnicodeScript};
+
+// Fonts tried in order for text SDL_ttf can't lay out on its own, the first one with a glyph
+// for a character is used
+static FALLBACK_FONTS: [&str; 12] = [
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/noto/NotoSansArabic-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansArabic-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSansHebrew-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansHebrew-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSansDevanagari-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansDevanagari-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSansThai-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansThai-Regular.ttf",
+    "/usr/share/fonts/gnu-free/FreeSerif.otf",
+    "/usr/share/fonts/truetype/freefont/FreeSerif.ttf",
+];
+
+// Colour fonts with bitmap (CBDT, sbix) or layered (COLR) glyphs, preferred for emoji
+static EMOJI_FONTS: [&str; 5] = [
+    "/usr/share/fonts/noto/NotoColorEmoji.ttf",
+    "/usr/share/fonts/truetype/noto/NotoColorEmoji.ttf",
+    "/usr/share/fonts/twemoji/twemoji.ttf",
+    "/usr/share/fonts/TTF/Twemoji.ttf",
+    "/usr/share/fonts/joypixels/JoyPixels.ttf",
+];
+
+// A font loaded once for the whole run of the browser, shaped with rustybuzz and
+// rasterized with ab_glyph
+struct ShapingFont {
+    face: Face<'static>,
+    font: FontRef<'static>,
+    // Whether the font has colour glyphs
+    color: bool,
+}
+
+thread_local! {
+    static FONTS: Vec<ShapingFont> = FALLBACK_FONTS
+        .iter()
+        .chain(EMOJI_FONTS.iter())
+        .filter_map(|path| {
+            let data: &'static [u8] = Box::leak(std::fs::read(path).ok()?.into_boxed_slice());
+            let face = Face::from_slice(data, 0)?;
+            let tables = face.tables();
+            let color = tables.colr.is_some() || tables.cbdt.is_some() || tables.sbix.is_some();
+            Some(ShapingFont {
+                face,
+                font: FontRef::try_from_slice(data).ok()?,
+                color,
+            })
+        })
+        .collect();
+}
+
+// Pictographs that are drawn from a colour font when there is one
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32, 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0x1F000..=0x1FAFF)
+}
+
+// Characters that belong to the character before them, like combining marks, variation
+// selectors, zero width joiners and skin tone modifiers
+fn joins_previous(c: char) -> bool {
+    c.script() == Script::Inherited || matches!(c as u32, 0x1F3FB..=0x1F3FF | 0xE0020..=0xE007F)
+}
+
+// Whether a character has to be drawn with a fallback font, either because it is of a script
+// whose letters change shape or order with their neighbours, which SDL_ttf draws one by one
+// from left to right, or because `font` has no glyph for it
+fn needs_fallback(font: &sdl2::ttf::Font, c: char) -> bool {
+    let simple_script = matches!(
+        c.script(),
+        Script::Common
+            | Script::Inherited
+            | Script::Unknown
+            | Script::Latin
+            | Script::Greek
+            | Script::Cyrillic
+            | Script::Han
+            | Script::Hiragana
+            | Script::Katakana
+            | Script::Hangul
+    );
+    !c.is_control() && (!simple_script || is_emoji(c) || font.find_glyph(c).is_none())
+}
+
+// Split `text` into runs SDL_ttf can draw with `font` and runs for the fallback fonts, marked
+// true, in logical order. Spaces and marks stay in the run before them.
+pub fn font_runs(font: &sdl2::ttf::Font, text: &str) -> Vec<(Range<usize>, bool)> {
+    let mut runs: Vec<(Range<usize>, bool)> = Vec::new();
+    for (i, c) in text.char_indices() {
+        let end = i + c.len_utf8();
+        match runs.last_mut() {
+            Some((range, _)) if c.is_whitespace() || joins_previous(c) => range.end = end,
+            Some((range, fallback)) if *fallback == needs_fallback(font, c) => range.end = end,
+            _ => runs.push((i..end, needs_fallback(font, c))),
+        }
+    }
+    runs
+}
+
+// Split `text` into runs of the fallback font used for each of them, preferring colour fonts
+// for emoji
+fn fallback_runs(fonts: &[ShapingFont], text: &str) -> Vec<(Range<usize>, usize)> {
+    let covers = |font: &ShapingFont, c: char| font.font.glyph_id(c) != GlyphId(0);
+    let mut runs: Vec<(Range<usize>, usize)> = Vec::new();
+    for (i, c) in text.char_indices() {
+        let end = i + c.len_utf8();
+        if let Some((range, font)) = runs.last_mut() {
+            if joins_previous(c) || (c.is_whitespace() && covers(&fonts[*font], c)) {
+                range.end = end;
+                continue;
+            }
+        }
+        let emoji = is_emoji(c);
+        let font = fonts
+            .iter()
+            .position(|font| font.color == emoji && covers(font, c))
+            .or_else(|| fonts.iter().position(|font| covers(font, c)))
+            .unwrap_or(0);
+        match runs.last_mut() {
+            Some((range, previous)) if *previous == font => range.end = end,
+            _ => runs.push((i..end, font)),
+        }
+    }
+    runs
+}
+
+// Glyph ids in visual order with their horizontal position and vertical offset in pixels
+type Glyphs = Vec<(u16, f32, f32)>;
+
+// Glyphs of `text` at `font_size` and their total advance
+fn shape(font: &ShapingFont, text: &str, font_size: u32, rtl: bool) -> (Glyphs, f32) {
+    let scale = font_size as f32 / font.font.height_unscaled();
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+    buffer.set_direction(if rtl {
+        Direction::RightToLeft
+    } else {
+        Direction::LeftToRight
+    });
+    let glyphs = rustybuzz::shape(&font.face, &[], buffer);
+    let mut pen = 0.0;
+    let mut positioned = Vec::with_capacity(glyphs.len());
+    for (info, position) in glyphs.glyph_infos().iter().zip(glyphs.glyph_positions()) {
+        positioned.push((
+            info.glyph_id as u16,
+            pen + position.x_offset as f32 * scale,
+            position.y_offset as f32 * scale,
+        ));
+        pen += position.x_advance as f32 * scale;
+    }
+    (positioned, pen)
+}
+
+// Width of shaped `text` at `font_size`, if there is a font to shape it with
+pub fn shaped_width(text: &str, font_size: u32, rtl: bool) -> Option<u32> {
+    FONTS.with(|fonts| {
+        if fonts.is_empty() {
+            return None;
+        }
+        let width: f32 = fallback_runs(fonts, text)
+            .into_iter()
+            .map(|(range, font)| shape(&fonts[font], &text[range], font_size, rtl).1)
+            .sum();
+        Some(width.ceil() as u32)
+    })
+}
+
+// Blend `color` over an RGBA pixel with `coverage` between 0 and 1
+fn blend(pixel: &mut [u8], color: Color, coverage: f32) {
+    let alpha = coverage.clamp(0.0, 1.0) * color.a as f32 / 255.0;
+    let below = pixel[3] as f32 / 255.0 * (1.0 - alpha);
+    let total = alpha + below;
+    if total <= 0.0 {
+        return;
+    }
+    let mix = |src: u8, dst: u8| ((src as f32 * alpha + dst as f32 * below) / total) as u8;
+    pixel.copy_from_slice(&[
+        mix(color.r, pixel[0]),
+        mix(color.g, pixel[1]),
+        mix(color.b, pixel[2]),
+        (total * 255.0) as u8,
+    ]);
+}
+
+// Rasterize the outline of a glyph with its origin at `x, y` into the surface
+fn draw_outline(surface: &mut Surface, font: &ShapingFont, id: u16, x: f32, y: f32, color: Color) {
+    let font_size = surface.height() as f32;
+    let glyph = GlyphId(id).with_scale_and_position(PxScale::from(font_size), point(x, y));
+    let outline = match font.font.outline_glyph(glyph) {
+        Some(outline) => outline,
+        None => return,
+    };
+    let bounds = outline.px_bounds();
+    let (width, height, pitch) = (
+        surface.width() as i32,
+        surface.height() as i32,
+        surface.pitch() as i32,
+    );
+    surface.with_lock_mut(|pixels| {
+        outline.draw(|gx, gy, coverage| {
+            let (px, py) = (
+                bounds.min.x as i32 + gx as i32,
+                bounds.min.y as i32 + gy as i32,
+            );
+            if px >= 0 && py >= 0 && px < width && py < height {
+                let i = (py * pitch + px * 4) as usize;
+                blend(&mut pixels[i..i + 4], color, coverage);
+            }
+        });
+    });
+}
+
+// Collects the layers of a COLR glyph. Only solid layers are supported, gradients are drawn in
+// the colour of their first stop and transforms and clips are ignored, which is all the first
+// version of the table uses.
+struct LayerPainter {
+    glyph: Option<u16>,
+    layers: Vec<(u16, RgbaColor)>,
+}
+impl<'a> Painter<'a> for LayerPainter {
+    fn outline_glyph(&mut self, glyph_id: ttf_parser::GlyphId) {
+        self.glyph = Some(glyph_id.0);
+    }
+    fn paint(&mut self, paint: Paint<'a>) {
+        let color = match paint {
+            Paint::Solid(color) => Some(color),
+            Paint::LinearGradient(gradient) => gradient.stops(0, &[]).next().map(|s| s.color),
+            Paint::RadialGradient(gradient) => gradient.stops(0, &[]).next().map(|s| s.color),
+            Paint::SweepGradient(gradient) => gradient.stops(0, &[]).next().map(|s| s.color),
+        };
+        if let (Some(glyph), Some(color)) = (self.glyph, color) {
+            self.layers.push((glyph, color));
+        }
+    }
+    fn push_clip(&mut self) {}
+    fn push_clip_box(&mut self, _: ClipBox) {}
+    fn pop_clip(&mut self) {
+        self.glyph = None;
+    }
+    fn push_layer(&mut self, _: CompositeMode) {}
+    fn pop_layer(&mut self) {}
+    fn push_transform(&mut self, _: Transform) {}
+    fn pop_transform(&mut self) {}
+}
+
+// Draw a colour glyph, returning false when the glyph has no colour version
+fn draw_color_glyph(
+    surface: &mut Surface,
+    font: &ShapingFont,
+    id: u16,
+    x: f32,
+    baseline: f32,
+    color: Color,
+) -> bool {
+    let glyph_id = ttf_parser::GlyphId(id);
+    // Units per pixel at this size
+    let scale = surface.height() as f32 / font.font.height_unscaled();
+    let pixels_per_em = (font.face.units_per_em() as f32 * scale).round() as u16;
+    if let Some(image) = font.face.glyph_raster_image(glyph_id, pixels_per_em) {
+        if image.format != RasterImageFormat::PNG {
+            return false;
+        }
+        let mut bitmap = match RWops::from_bytes(image.data).and_then(|data| data.load_png()) {
+            Ok(bitmap) => bitmap,
+            Err(_) => return false,
+        };
+        let factor = pixels_per_em as f32 / image.pixels_per_em as f32;
+        let target = Rect::new(
+            (x + image.x as f32 * factor).round() as i32,
+            (baseline - (image.y as f32 + image.height as f32) * factor).round() as i32,
+            (image.width as f32 * factor).round().max(1.0) as u32,
+            (image.height as f32 * factor).round().max(1.0) as u32,
+        );
+        bitmap.set_blend_mode(BlendMode::Blend).unwrap();
+        return bitmap.blit_scaled(None, surface, target).is_ok();
+    }
+    if !font.face.is_color_glyph(glyph_id) {
+        return false;
+    }
+    let mut painter = LayerPainter {
+        glyph: None,
+        layers: Vec::new(),
+    };
+    let foreground = RgbaColor::new(color.r, color.g, color.b, color.a);
+    if font
+        .face
+        .paint_color_glyph(glyph_id, 0, foreground, &mut painter)
+        .is_none()
+    {
+        return false;
+    }
+    for (layer, color) in painter.layers {
+        let color = Color::RGBA(color.red, color.green, color.blue, color.alpha);
+        draw_outline(surface, font, layer, x, baseline, color);
+    }
+    true
+}
+
+// Shape and rasterize `text` into an RGBA surface `font_size` high, if there are fallback fonts
+pub fn render_shaped(
+    text: &str,
+    font_size: u32,
+    color: Color,
+    rtl: bool,
+) -> Option<Surface<'static>> {
+    FONTS.with(|fonts| {
+        if fonts.is_empty() {
+            return None;
+        }
+        let mut runs: Vec<(&ShapingFont, Glyphs, f32)> = fallback_runs(fonts, text)
+            .into_iter()
+            .map(|(range, font)| {
+                let (glyphs, advance) = shape(&fonts[font], &text[range], font_size, rtl);
+                (&fonts[font], glyphs, advance)
+            })
+            .collect();
+        if rtl {
+            runs.reverse();
+        }
+        let width: f32 = runs.iter().map(|(_, _, advance)| advance).sum();
+        let mut surface = Surface::new(
+            (width.ceil() as u32).max(1),
+            font_size,
+            PixelFormatEnum::RGBA32,
+        )
+        .ok()?;
+        let mut pen = 0.0;
+        for (font, glyphs, advance) in runs {
+            let scale = font_size as f32 / font.font.height_unscaled();
+            let baseline = font.font.ascent_unscaled() * scale;
+            for (id, x, y) in glyphs {
+                if font.color
+                    && draw_color_glyph(&mut surface, font, id, pen + x, baseline - y, color)
+                {
+                    continue;
+                }
+                draw_outline(&mut surface, font, id, pen + x, baseline - y, color);
+            }
+            pen += advance;
+        }
+        Some(surface)
+    })
+}
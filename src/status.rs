@@ -0,0 +1,37 @@
+use sdl2::rect::Rect;
+
+use crate::find::bar_height;
+use crate::renderer::{draw_text, text_color, text_width, RendererContext};
+use crate::style::Style;
+
+// Draw `text` in a strip at the bottom left of the canvas, above the find bar when it is open
+pub fn paint_status(context: &RendererContext, text: &str) {
+    let (screen_width, screen_height) = context.canvas.borrow().output_size().unwrap();
+    let sf = context.scaling_factor;
+    let style = Style::default();
+    let font_size = 14 * sf;
+    let width = (text_width(&context.font.borrow(), text, font_size) + 12 * sf).min(screen_width);
+    let height = font_size + 8 * sf;
+    let bottom = match context.find {
+        Some(_) => screen_height - bar_height(context),
+        None => screen_height,
+    };
+    let top = bottom.saturating_sub(height) as i32;
+    {
+        let mut canvas = context.canvas.borrow_mut();
+        canvas.set_draw_color(context.color_scheme.background);
+        canvas.fill_rect(Rect::new(0, top, width, height)).unwrap();
+        canvas.set_draw_color(context.color_scheme.text);
+        canvas.draw_rect(Rect::new(0, top, width, height)).unwrap();
+    }
+    let color = text_color(context, &style);
+    draw_text(
+        context,
+        text,
+        &style,
+        font_size,
+        color,
+        6 * sf as i32,
+        top + 4 * sf as i32,
+    );
+}
@@ -0,0 +1,742 @@
+use std::str::FromStr;
+
+use rcdom::Handle;
+use rcdom::NodeData;
+use sdl2::pixels::Color;
+use sdl2::ttf::FontStyle;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhiteSpace {
+    Normal,
+    NoWrap,
+    Pre,
+    PreWrap,
+    PreLine,
+}
+impl WhiteSpace {
+    pub fn preserves_newlines(&self) -> bool {
+        matches!(
+            self,
+            WhiteSpace::Pre | WhiteSpace::PreWrap | WhiteSpace::PreLine
+        )
+    }
+    pub fn preserves_spaces(&self) -> bool {
+        matches!(self, WhiteSpace::Pre | WhiteSpace::PreWrap)
+    }
+    pub fn wraps(&self) -> bool {
+        !matches!(self, WhiteSpace::Pre | WhiteSpace::NoWrap)
+    }
+}
+impl FromStr for WhiteSpace {
+    type Err = String;
+    fn from_str(value: &str) -> Result<WhiteSpace, String> {
+        match value {
+            "normal" => Ok(WhiteSpace::Normal),
+            "nowrap" => Ok(WhiteSpace::NoWrap),
+            "pre" => Ok(WhiteSpace::Pre),
+            "pre-wrap" => Ok(WhiteSpace::PreWrap),
+            "pre-line" => Ok(WhiteSpace::PreLine),
+            _ => Err(format!("Unknown white-space value: {}", value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    // Left in left-to-right text and right in right-to-left text
+    Start,
+    End,
+    Left,
+    Right,
+    Center,
+    Justify,
+}
+impl FromStr for TextAlign {
+    type Err = String;
+    fn from_str(value: &str) -> Result<TextAlign, String> {
+        match value {
+            "start" => Ok(TextAlign::Start),
+            "end" => Ok(TextAlign::End),
+            "left" => Ok(TextAlign::Left),
+            "right" => Ok(TextAlign::Right),
+            "center" | "middle" => Ok(TextAlign::Center),
+            "justify" => Ok(TextAlign::Justify),
+            _ => Err(format!("Unknown text-align value: {}", value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineHeight {
+    // As high as the font size
+    Normal,
+    // A multiple of the font size of each piece of text
+    Number(f32),
+    // In CSS pixels
+    Length(u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Ltr,
+    Rtl,
+}
+
+// How an element takes part in the bidi algorithm, see CSS `unicode-bidi`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnicodeBidi {
+    Normal,
+    Embed,
+    Isolate,
+    // An isolate whose direction comes from its first strong character, like `dir="auto"`
+    IsolateAuto,
+    // Lays out every character in the element's direction, like `<bdo>`
+    Override,
+    IsolateOverride,
+}
+
+// Inherited properties, saved before descending into an element and restored afterwards
+#[derive(Clone, Debug)]
+pub struct Style {
+    pub white_space: WhiteSpace,
+    pub monospace: bool,
+    pub font_style: FontStyle,
+    // In CSS pixels, multiplied by the scaling factor when drawing
+    pub font_size: u32,
+    // Distance the baseline is moved down (sub) or up (negative, sup) in CSS pixels
+    pub baseline_shift: i32,
+    // Target of the enclosing link, as written in the document
+    pub href: Option<String>,
+    // Whether the enclosing link goes to a location that has been shown, like `:visited`
+    pub visited: bool,
+    // Index of the enclosing focusable element in the renderer context
+    pub focus: Option<usize>,
+    // Index of the enclosing form in the renderer context
+    pub form: Option<usize>,
+    pub highlight: bool,
+    pub heading: bool,
+    pub text_align: TextAlign,
+    pub line_height: LineHeight,
+    // In CSS pixels, added after every character and to every space
+    pub letter_spacing: i32,
+    pub word_spacing: i32,
+    // In CSS pixels, indentation of the first line of blocks
+    pub text_indent: i32,
+    pub direction: Direction,
+    // Bidi embeddings, isolates and overrides around the text, outermost first, with the id
+    // of the element that started them
+    pub embeddings: Vec<(usize, UnicodeBidi, Direction)>,
+}
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            white_space: WhiteSpace::Normal,
+            monospace: false,
+            font_style: FontStyle::NORMAL,
+            font_size: 16,
+            baseline_shift: 0,
+            href: None,
+            visited: false,
+            focus: None,
+            form: None,
+            highlight: false,
+            heading: false,
+            text_align: TextAlign::Start,
+            line_height: LineHeight::Normal,
+            letter_spacing: 0,
+            word_spacing: 0,
+            text_indent: 0,
+            direction: Direction::Ltr,
+            embeddings: Vec::new(),
+        }
+    }
+}
+impl Style {
+    // Derive the style of an element's contents from its tag and `style` attribute
+    pub fn for_element(&self, handle: &Handle) -> Style {
+        let mut style = self.clone();
+        if let NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } = handle.data
+        {
+            match &*name.local {
+                "pre" | "listing" | "xmp" | "plaintext" => {
+                    style.monospace = true;
+                    style.white_space = WhiteSpace::Pre;
+                }
+                "code" | "kbd" | "samp" | "tt" => style.monospace = true,
+                "nobr" => style.white_space = WhiteSpace::NoWrap,
+                "b" | "strong" => style.font_style |= FontStyle::BOLD,
+                "i" | "em" | "cite" | "dfn" | "var" | "address" => {
+                    style.font_style |= FontStyle::ITALIC
+                }
+                "u" | "ins" => style.font_style |= FontStyle::UNDERLINE,
+                "s" | "strike" | "del" => style.font_style |= FontStyle::STRIKETHROUGH,
+                "mark" => style.highlight = true,
+                "center" => style.text_align = TextAlign::Center,
+                "small" => style.font_size = style.font_size * 5 / 6,
+                "big" => style.font_size = style.font_size * 6 / 5,
+                "sub" => {
+                    style.baseline_shift += style.font_size as i32 / 4;
+                    style.font_size = style.font_size * 5 / 6;
+                }
+                "sup" => {
+                    style.baseline_shift -= style.font_size as i32 / 3;
+                    style.font_size = style.font_size * 5 / 6;
+                }
+                "a" => {
+                    if let Some(href) = attrs.borrow().iter().find(|a| &a.name.local == "href") {
+                        style.href = Some(href.value.trim().to_string());
+                        style.font_style |= FontStyle::UNDERLINE;
+                    }
+                }
+                "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                    let font_sizes = [32, 24, 19, 16, 13, 11];
+                    let level = name.local[1..].parse::<usize>().unwrap();
+                    style.font_size = font_sizes[level - 1];
+                    style.font_style |= FontStyle::BOLD;
+                    style.heading = true;
+                }
+                _ => {}
+            }
+            // The legacy `align` attribute of blocks, which `img` and others use for floats
+            if let Some(align) = attrs.borrow().iter().find(|a| &a.name.local == "align") {
+                let aligns_text = [
+                    "div", "p", "h1", "h2", "h3", "h4", "h5", "h6", "td", "th", "tr", "caption",
+                    "legend",
+                ];
+                if aligns_text.contains(&&*name.local) {
+                    if let Ok(text_align) = align.value.to_ascii_lowercase().parse() {
+                        style.text_align = text_align;
+                    }
+                }
+            }
+            if let Some(dir) = attrs.borrow().iter().find(|a| &a.name.local == "dir") {
+                match &*dir.value.to_ascii_lowercase() {
+                    "ltr" => style.direction = Direction::Ltr,
+                    "rtl" => style.direction = Direction::Rtl,
+                    _ => {}
+                }
+            }
+            if let Some(attr) = attrs.borrow().iter().find(|a| &a.name.local == "style") {
+                for (property, value) in parse_declarations(&attr.value) {
+                    match property.as_str() {
+                        "text-align" => {
+                            if let Ok(text_align) = value.parse() {
+                                style.text_align = text_align;
+                            }
+                        }
+                        "line-height" => {
+                            if value == "normal" {
+                                style.line_height = LineHeight::Normal;
+                            } else if let Ok(number) = value.parse::<f32>() {
+                                style.line_height = LineHeight::Number(number.max(0.0));
+                            } else if let Some(percent) =
+                                value.strip_suffix('%').and_then(|p| p.parse::<f32>().ok())
+                            {
+                                let length = style.font_size as f32 * percent / 100.0;
+                                style.line_height = LineHeight::Length(length.max(0.0) as u32);
+                            } else if let Some(length) = parse_length(&value, style.font_size) {
+                                style.line_height = LineHeight::Length(length.max(0) as u32);
+                            }
+                        }
+                        "letter-spacing" => {
+                            style.letter_spacing =
+                                parse_length(&value, style.font_size).unwrap_or(0)
+                        }
+                        "word-spacing" => {
+                            style.word_spacing = parse_length(&value, style.font_size).unwrap_or(0)
+                        }
+                        "text-indent" => {
+                            if let Some(indent) = parse_length(&value, style.font_size) {
+                                style.text_indent = indent;
+                            }
+                        }
+                        "direction" => match value.as_str() {
+                            "ltr" => style.direction = Direction::Ltr,
+                            "rtl" => style.direction = Direction::Rtl,
+                            _ => {}
+                        },
+                        "white-space" => {
+                            if let Ok(white_space) = value.parse() {
+                                style.white_space = white_space;
+                            }
+                        }
+                        "font-weight" => match value.as_str() {
+                            "bold" | "bolder" | "600" | "700" | "800" | "900" => {
+                                style.font_style |= FontStyle::BOLD
+                            }
+                            "normal" | "lighter" | "100" | "200" | "300" | "400" | "500" => {
+                                style.font_style -= FontStyle::BOLD
+                            }
+                            _ => {}
+                        },
+                        "font-style" => match value.as_str() {
+                            "italic" | "oblique" => style.font_style |= FontStyle::ITALIC,
+                            "normal" => style.font_style -= FontStyle::ITALIC,
+                            _ => {}
+                        },
+                        "text-decoration" | "text-decoration-line" => {
+                            style.font_style -= FontStyle::UNDERLINE | FontStyle::STRIKETHROUGH;
+                            for line in value.split_whitespace() {
+                                match line {
+                                    "underline" => style.font_style |= FontStyle::UNDERLINE,
+                                    "line-through" => style.font_style |= FontStyle::STRIKETHROUGH,
+                                    _ => {}
+                                }
+                            }
+                        }
+                        "font-family" => {
+                            style.monospace = value.split(',').any(|f| {
+                                f.trim().trim_matches(|c| c == '"' || c == '\'') == "monospace"
+                            })
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        style
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Display {
+    Block,
+    Inline,
+    None,
+}
+
+// Non-inherited box properties of a single element, all edges in CSS pixels
+// and ordered top, right, bottom, left
+#[derive(Clone, Debug)]
+pub struct BoxStyle {
+    pub display: Display,
+    pub margin: [i32; 4],
+    pub padding: [u32; 4],
+    pub border: [u32; 4],
+    // None draws the border in the text colour
+    pub border_color: [Option<Color>; 4],
+    pub background: Option<Color>,
+    pub unicode_bidi: UnicodeBidi,
+}
+impl BoxStyle {
+    pub fn for_element(handle: &Handle, font_size: u32) -> BoxStyle {
+        let mut box_style = BoxStyle {
+            display: Display::Inline,
+            margin: [0; 4],
+            padding: [0; 4],
+            border: [0; 4],
+            border_color: [None; 4],
+            background: None,
+            unicode_bidi: UnicodeBidi::Normal,
+        };
+        let (name, attrs) = match handle.data {
+            NodeData::Element {
+                ref name,
+                ref attrs,
+                ..
+            } => (name, attrs),
+            _ => return box_style,
+        };
+        // A border is only drawn when its style is set, medium (3px) if no width is given
+        let mut border_visible = [false; 4];
+        let mut border_width = [3; 4];
+        if BLOCK_TAGS.contains(&&*name.local) {
+            box_style.display = Display::Block;
+        }
+        match &*name.local {
+            "body" => box_style.margin = [8; 4],
+            "p" | "dl" | "pre" | "listing" | "xmp" | "plaintext" => {
+                box_style.margin = [16, 0, 16, 0]
+            }
+            "blockquote" | "figure" => box_style.margin = [16, 40, 16, 40],
+            "ul" | "ol" | "menu" => {
+                box_style.margin = [16, 0, 16, 0];
+                box_style.padding[3] = 40;
+            }
+            "dd" => box_style.margin[3] = 40,
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let heading_margins = [21, 20, 19, 21, 22, 25];
+                let level = name.local[1..].parse::<usize>().unwrap();
+                box_style.margin = [heading_margins[level - 1], 0, heading_margins[level - 1], 0];
+            }
+            "hr" => {
+                box_style.margin = [8, 0, 8, 0];
+                border_visible = [true; 4];
+                border_width = [1; 4];
+                box_style.border_color = [Some(Color::RGB(128, 128, 128)); 4];
+            }
+            "fieldset" => {
+                box_style.margin = [0, 2, 0, 2];
+                box_style.padding = [6, 12, 12, 12];
+                border_visible = [true; 4];
+                border_width = [2; 4];
+                box_style.border_color = [Some(Color::RGB(192, 192, 192)); 4];
+            }
+            _ => {}
+        }
+        for attr in attrs.borrow().iter() {
+            if &attr.name.local == "bgcolor" {
+                box_style.background = parse_color(&attr.value.to_lowercase());
+            }
+            // Elements with a direction are isolated from the text around them
+            if &attr.name.local == "dir" {
+                box_style.unicode_bidi = match &*attr.value.to_ascii_lowercase() {
+                    "ltr" | "rtl" => UnicodeBidi::Isolate,
+                    "auto" => UnicodeBidi::IsolateAuto,
+                    _ => box_style.unicode_bidi,
+                };
+            }
+        }
+        match &*name.local {
+            "bdi" if box_style.unicode_bidi == UnicodeBidi::Normal => {
+                box_style.unicode_bidi = UnicodeBidi::IsolateAuto
+            }
+            "bdo" => box_style.unicode_bidi = UnicodeBidi::IsolateOverride,
+            _ => {}
+        }
+        let declarations = attrs
+            .borrow()
+            .iter()
+            .find(|a| &a.name.local == "style")
+            .map(|attr| parse_declarations(&attr.value))
+            .unwrap_or_default();
+        for (property, value) in declarations {
+            let length = |value: &str| parse_length(value, font_size);
+            let values: Vec<&str> = value.split_whitespace().collect();
+            let side = ["top", "right", "bottom", "left"]
+                .iter()
+                .position(|side| property.split('-').any(|part| part == *side));
+            match (property.as_str(), side) {
+                ("display", _) => match value.as_str() {
+                    "none" => box_style.display = Display::None,
+                    "block" | "list-item" | "flex" | "grid" | "table" => {
+                        box_style.display = Display::Block
+                    }
+                    "inline" | "inline-block" | "inline-flex" => {
+                        box_style.display = Display::Inline
+                    }
+                    _ => {}
+                },
+                ("margin", _) => {
+                    if let Some(margin) = edges(values.iter().map(|v| length(v).unwrap_or(0))) {
+                        box_style.margin = margin;
+                    }
+                }
+                ("padding", _) => {
+                    if let Some(padding) = edges(values.iter().map(|v| length(v).unwrap_or(0))) {
+                        box_style.padding = padding.map(|p| p.max(0) as u32);
+                    }
+                }
+                (property, Some(side)) if property.starts_with("margin-") => {
+                    box_style.margin[side] = length(&value).unwrap_or(0);
+                }
+                (property, Some(side)) if property.starts_with("padding-") => {
+                    box_style.padding[side] = length(&value).unwrap_or(0).max(0) as u32;
+                }
+                ("unicode-bidi", _) => match value.as_str() {
+                    "normal" => box_style.unicode_bidi = UnicodeBidi::Normal,
+                    "embed" => box_style.unicode_bidi = UnicodeBidi::Embed,
+                    "isolate" => box_style.unicode_bidi = UnicodeBidi::Isolate,
+                    "plaintext" => box_style.unicode_bidi = UnicodeBidi::IsolateAuto,
+                    "bidi-override" => box_style.unicode_bidi = UnicodeBidi::Override,
+                    "isolate-override" => box_style.unicode_bidi = UnicodeBidi::IsolateOverride,
+                    _ => {}
+                },
+                ("background" | "background-color", _) => {
+                    box_style.background = values.iter().find_map(|v| parse_color(v));
+                }
+                ("border-width", _) => {
+                    if let Some(width) = edges(values.iter().map(|v| border_length(v, font_size))) {
+                        border_width = width;
+                    }
+                }
+                ("border-style", _) => {
+                    if let Some(visible) = edges(values.iter().map(|v| border_style_visible(v))) {
+                        border_visible = visible;
+                    }
+                }
+                ("border-color", _) => {
+                    if let Some(color) = edges(values.iter().map(|v| parse_color(v))) {
+                        box_style.border_color = color;
+                    }
+                }
+                (property, side) if property == "border" || property.starts_with("border-") => {
+                    let sides = match side {
+                        Some(side) => side..side + 1,
+                        None if property == "border" => 0..4,
+                        None => continue,
+                    };
+                    let property = property.trim_start_matches("border");
+                    for side in sides {
+                        match property.rsplit('-').next().unwrap() {
+                            "radius" => {}
+                            "width" => border_width[side] = border_length(&value, font_size),
+                            "style" => border_visible[side] = border_style_visible(&value),
+                            "color" => box_style.border_color[side] = parse_color(&value),
+                            _ => {
+                                border_visible[side] = false;
+                                for v in &values {
+                                    if let Some(color) = parse_color(v) {
+                                        box_style.border_color[side] = Some(color);
+                                    } else if is_border_style(v) {
+                                        border_visible[side] = border_style_visible(v);
+                                    } else {
+                                        border_width[side] = border_length(v, font_size);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for side in 0..4 {
+            box_style.border[side] = if border_visible[side] {
+                border_width[side]
+            } else {
+                0
+            };
+        }
+        box_style
+    }
+}
+
+static BLOCK_TAGS: [&str; 43] = [
+    "html",
+    "body",
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "details",
+    "dialog",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "tr",
+    "ul",
+    "legend",
+    "listing",
+    "xmp",
+    "plaintext",
+    "menu",
+    "hgroup",
+];
+
+// Expand the 1-4 values of a shorthand like `margin` to top, right, bottom and left
+fn edges<T: Copy>(values: impl Iterator<Item = T>) -> Option<[T; 4]> {
+    match values.collect::<Vec<T>>()[..] {
+        [all] => Some([all; 4]),
+        [vertical, horizontal] => Some([vertical, horizontal, vertical, horizontal]),
+        [top, horizontal, bottom] => Some([top, horizontal, bottom, horizontal]),
+        [top, right, bottom, left] => Some([top, right, bottom, left]),
+        _ => None,
+    }
+}
+
+fn is_border_style(value: &str) -> bool {
+    [
+        "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset",
+        "outset",
+    ]
+    .contains(&value)
+}
+
+fn border_style_visible(value: &str) -> bool {
+    is_border_style(value) && value != "none" && value != "hidden"
+}
+
+fn border_length(value: &str, font_size: u32) -> u32 {
+    match value {
+        "thin" => 1,
+        "medium" => 3,
+        "thick" => 5,
+        _ => parse_length(value, font_size).unwrap_or(3).max(0) as u32,
+    }
+}
+
+// Convert a CSS length to pixels, relative units are resolved against `font_size`
+pub fn parse_length(value: &str, font_size: u32) -> Option<i32> {
+    let units = [
+        ("px", 1.0),
+        ("rem", 16.0),
+        ("em", font_size as f32),
+        ("pt", 4.0 / 3.0),
+        ("pc", 16.0),
+        ("in", 96.0),
+        ("cm", 96.0 / 2.54),
+        ("mm", 96.0 / 25.4),
+    ];
+    if value == "0" {
+        return Some(0);
+    }
+    units.iter().find_map(|(unit, factor)| {
+        let number: f32 = value.strip_suffix(unit)?.parse().ok()?;
+        Some((number * factor).round() as i32)
+    })
+}
+
+// Parse hex, rgb()/rgba() and the common named colours, `transparent` has no colour
+pub fn parse_color(value: &str) -> Option<Color> {
+    if let Some(hex) = value.strip_prefix('#') {
+        // Checked first, as the digits are sliced by byte and from_str_radix allows a sign
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok();
+        let pair = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return match hex.len() {
+            3 | 4 => Some(Color::RGB(digit(0)? * 17, digit(1)? * 17, digit(2)? * 17)),
+            6 | 8 => Some(Color::RGB(pair(0)?, pair(2)?, pair(4)?)),
+            _ => None,
+        };
+    }
+    if let Some(arguments) = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))
+        .and_then(|v| v.strip_suffix(')'))
+    {
+        let channels: Vec<u8> = arguments
+            .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+            .filter(|c| !c.is_empty())
+            .take(3)
+            .filter_map(|c| match c.strip_suffix('%') {
+                Some(percent) => percent
+                    .parse::<f32>()
+                    .ok()
+                    .map(|p| (p * 2.55).round() as u8),
+                None => c.parse::<f32>().ok().map(|c| c.round() as u8),
+            })
+            .collect();
+        return match channels[..] {
+            [r, g, b] => Some(Color::RGB(r, g, b)),
+            _ => None,
+        };
+    }
+    let (r, g, b) = match value {
+        "black" => (0, 0, 0),
+        "silver" => (192, 192, 192),
+        "gray" | "grey" => (128, 128, 128),
+        "white" => (255, 255, 255),
+        "maroon" => (128, 0, 0),
+        "red" => (255, 0, 0),
+        "purple" => (128, 0, 128),
+        "fuchsia" | "magenta" => (255, 0, 255),
+        "green" => (0, 128, 0),
+        "lime" => (0, 255, 0),
+        "olive" => (128, 128, 0),
+        "yellow" => (255, 255, 0),
+        "navy" => (0, 0, 128),
+        "blue" => (0, 0, 255),
+        "teal" => (0, 128, 128),
+        "aqua" | "cyan" => (0, 255, 255),
+        "orange" => (255, 165, 0),
+        "pink" => (255, 192, 203),
+        "brown" => (165, 42, 42),
+        "lightgray" | "lightgrey" => (211, 211, 211),
+        "darkgray" | "darkgrey" => (169, 169, 169),
+        "whitesmoke" => (245, 245, 245),
+        "beige" => (245, 245, 220),
+        "ivory" => (255, 255, 240),
+        "lightyellow" => (255, 255, 224),
+        "lightblue" => (173, 216, 230),
+        "lightgreen" => (144, 238, 144),
+        "gold" => (255, 215, 0),
+        "indigo" => (75, 0, 130),
+        "violet" => (238, 130, 238),
+        _ => return None,
+    };
+    Some(Color::RGB(r, g, b))
+}
+
+// Split a `style` attribute into lowercase (property, value) pairs
+pub fn parse_declarations(css: &str) -> Vec<(String, String)> {
+    css.split(';')
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            let value = value.trim().trim_end_matches("!important").trim();
+            Some((property.trim().to_lowercase(), value.to_lowercase()))
+        })
+        .filter(|(property, value)| !property.is_empty() && !value.is_empty())
+        .collect()
+}
+
+// Whether a media query list matches the screen. Only media types and `prefers-color-scheme`
+// are understood, queries with any other feature don't match.
+pub fn media_matches(query: &str, dark: bool) -> bool {
+    let query = query.trim().to_lowercase();
+    query.is_empty()
+        || query.split(',').any(|query| {
+            let query = query.trim();
+            let (negated, query) = match query.strip_prefix("not ") {
+                Some(query) => (true, query),
+                None => (false, query.strip_prefix("only ").unwrap_or(query)),
+            };
+            let matches = query.split(" and ").all(|part| match part.trim() {
+                "all" | "screen" => true,
+                feature if feature.starts_with('(') && feature.ends_with(')') => {
+                    match feature[1..feature.len() - 1].split_once(':') {
+                        Some((name, value)) if name.trim() == "prefers-color-scheme" => {
+                            value.trim() == if dark { "dark" } else { "light" }
+                        }
+                        _ => false,
+                    }
+                }
+                _ => false,
+            });
+            matches != negated
+        })
+}
+
+// Replace tabs with spaces up to the next multiple of 8 columns
+pub fn expand_tabs(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut column = 0;
+    for c in text.chars() {
+        match c {
+            '\t' => {
+                let spaces = 8 - column % 8;
+                expanded.push_str(&" ".repeat(spaces));
+                column += spaces;
+            }
+            '\n' => {
+                expanded.push(c);
+                column = 0;
+            }
+            _ => {
+                expanded.push(c);
+                column += 1;
+            }
+        }
+    }
+    expanded
+}
@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use url::Url;
+
+// Locations that have been shown, kept in a file with one location per line so that links to
+// them are drawn in the visited colour in later runs too
+#[derive(Clone, Default)]
+pub struct VisitedLinks {
+    locations: HashSet<String>,
+    // File new locations are appended to, if any
+    path: Option<PathBuf>,
+}
+
+// Where the visited locations are kept unless another file is given
+pub fn default_path() -> Option<PathBuf> {
+    Some(dirs::data_dir()?.join("opilio").join("visited"))
+}
+
+impl VisitedLinks {
+    // A store appending to `path`, starting with the locations saved there
+    pub fn load(path: Option<PathBuf>) -> VisitedLinks {
+        let locations = match &path {
+            Some(path) => fs::read_to_string(path).unwrap_or_default(),
+            None => String::new(),
+        };
+        VisitedLinks {
+            locations: locations.lines().map(|line| line.to_string()).collect(),
+            path,
+        }
+    }
+
+    pub fn contains(&self, location: &Url) -> bool {
+        self.locations.contains(location.as_str())
+    }
+
+    // Remember a location that was navigated to, saving it straight away. Returns whether it
+    // wasn't visited before.
+    pub fn add(&mut self, location: &Url) -> bool {
+        if location.scheme() == "about" {
+            return false;
+        }
+        let key = location.to_string();
+        if !self.locations.insert(key.clone()) {
+            return false;
+        }
+        let path = match &self.path {
+            Some(path) => path,
+            None => return true,
+        };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", key));
+        if let Err(e) = result {
+            println!("Couldn't save visited link to {}: {}", path.display(), e);
+        }
+        true
+    }
+}